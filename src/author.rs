@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+
+/// Lowercase words which start a surname, as in "Ursula K. Le Guin"
+const PARTICLES: [&str; 14] = [
+    "da", "de", "del", "della", "der", "des", "di", "du", "la", "le", "st", "van", "von", "ten",
+];
+
+/// Suffixes following a name, as in "Kurt Vonnegut, Jr.", compared without their full stop
+const SUFFIXES: [&str; 5] = ["jr", "sr", "ii", "iii", "iv"];
+
+/// Canonical record for an author, gathering the other names they appear under
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Author {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+impl Author {
+    pub fn new(name: &str) -> Author {
+        Author {
+            name: display_name(name),
+            sort_name: None,
+            aliases: Vec::new(),
+        }
+    }
+    pub fn sort_name(&self) -> String {
+        self.sort_name
+            .clone()
+            .unwrap_or_else(|| sort_name(&self.name))
+    }
+    /// Whether the author goes by a name, as their name or an alias
+    pub fn is_called(&self, name: &str) -> bool {
+        same_name(&self.name, name) || self.aliases.iter().any(|a| same_name(a, name))
+    }
+}
+
+fn words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn is_suffix(word: &str) -> bool {
+    SUFFIXES.contains(&word.trim().trim_end_matches('.').to_lowercase().as_str())
+}
+
+/// Split a suffix such as "Jr." from the end of a name, after a comma or a space
+fn split_suffix(name: &str) -> (&str, Option<&str>) {
+    let name = name.trim();
+    [name.rsplit_once(','), name.rsplit_once(' ')]
+        .into_iter()
+        .flatten()
+        .find(|(rest, suffix)| is_suffix(suffix) && !rest.trim().is_empty())
+        .map_or((name, None), |(rest, suffix)| {
+            (rest.trim(), Some(suffix.trim()))
+        })
+}
+
+fn with_suffix(name: String, suffix: Option<&str>) -> String {
+    match suffix {
+        Some(suffix) => format!("{}, {}", name, suffix),
+        None => name,
+    }
+}
+
+/// A name in reading order, turning "Dickens, Charles" into "Charles Dickens"
+pub fn display_name(name: &str) -> String {
+    let (name, suffix) = split_suffix(name);
+    let name = match name.split_once(',') {
+        Some((surname, given)) if !given.contains(',') && !given.trim().is_empty() => {
+            format!("{} {}", given.trim(), surname.trim())
+        }
+        _ => name.to_string(),
+    };
+    with_suffix(name, suffix)
+}
+
+/// A name surname first for sorting, turning "Ursula K. Le Guin" into "Le Guin, Ursula K."
+pub fn sort_name(name: &str) -> String {
+    let (name, suffix) = split_suffix(name);
+    if name.contains(',') {
+        return with_suffix(name.to_string(), suffix);
+    }
+    let parts: Vec<&str> = name.split_whitespace().collect();
+    let Some(mut start) = parts.len().checked_sub(1) else {
+        return String::new();
+    };
+    while start > 1 && PARTICLES.contains(&parts[start - 1].to_lowercase().as_str()) {
+        start -= 1;
+    }
+    let name = match start {
+        0 => name.to_string(),
+        _ => format!("{}, {}", parts[start..].join(" "), parts[..start].join(" ")),
+    };
+    with_suffix(name, suffix)
+}
+
+/// Names compared ignoring case, punctuation and surname first order
+pub fn same_name(a: &str, b: &str) -> bool {
+    words(&display_name(a)) == words(&display_name(b))
+}
+
+/// Whether one name could abbreviate the other, as "C. Dickens" does "Charles Dickens"
+pub fn could_be(a: &str, b: &str) -> bool {
+    let (a, b) = (
+        display_name(split_suffix(a).0),
+        display_name(split_suffix(b).0),
+    );
+    match (words(&a).split_last(), words(&b).split_last()) {
+        (Some((surname_a, given_a)), Some((surname_b, given_b))) => {
+            surname_a == surname_b
+                && !given_a.is_empty()
+                && !given_b.is_empty()
+                && given_a
+                    .iter()
+                    .zip(given_b)
+                    .all(|(x, y)| x.starts_with(y.as_str()) || y.starts_with(x.as_str()))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(display_name("Dickens, Charles"), "Charles Dickens");
+        assert_eq!(display_name("Charles Dickens"), "Charles Dickens");
+        assert_eq!(sort_name("Charles Dickens"), "Dickens, Charles");
+        assert_eq!(sort_name("Ursula K. Le Guin"), "Le Guin, Ursula K.");
+        assert_eq!(sort_name("Le Guin, Ursula K."), "Le Guin, Ursula K.");
+        assert_eq!(sort_name("Homer"), "Homer");
+
+        // Suffixes stay at the end
+        assert_eq!(display_name("Kurt Vonnegut, Jr."), "Kurt Vonnegut, Jr.");
+        assert_eq!(display_name("Vonnegut, Kurt, Jr."), "Kurt Vonnegut, Jr.");
+        assert_eq!(sort_name("Kurt Vonnegut, Jr."), "Vonnegut, Kurt, Jr.");
+        assert_eq!(
+            sort_name("Martin Luther King Jr."),
+            "King, Martin Luther, Jr."
+        );
+        assert_eq!(display_name("Vonnegut, Kurt Jr."), "Kurt Vonnegut, Jr.");
+        assert!(same_name("Vonnegut, Kurt, Jr.", "Kurt Vonnegut Jr."));
+        assert!(could_be("K. Vonnegut", "Kurt Vonnegut, Jr."));
+
+        assert!(same_name("Dickens, Charles", "charles  dickens"));
+        assert!(!same_name("C. Dickens", "Charles Dickens"));
+        assert!(could_be("C. Dickens", "Charles Dickens"));
+        assert!(could_be("Dickens, C.", "Charles John Huffam Dickens"));
+        assert!(!could_be("M. Dickens", "Charles Dickens"));
+        assert!(!could_be("Dickens", "Charles Dickens"));
+    }
+
+    #[test]
+    fn is_called() {
+        let mut a = Author::new("Dickens, Charles");
+        a.aliases.push("Boz".to_string());
+        assert_eq!(a.name, "Charles Dickens");
+        assert_eq!(a.sort_name(), "Dickens, Charles");
+        assert!(a.is_called("Dickens, Charles"));
+        assert!(a.is_called("boz"));
+        assert!(!a.is_called("C. Dickens"));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::error::Error;
+use chrono::{DateTime, Local};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Number of backups kept when saving, unless configured otherwise
+pub const DEFAULT_BACKUPS: usize = 3;
+
+#[derive(Debug)]
+pub struct Backup {
+    pub number: usize,
+    pub path: PathBuf,
+    pub modified: Option<DateTime<Local>>,
+}
+
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+pub fn backup_path(path: &Path, number: usize) -> PathBuf {
+    with_suffix(path, &format!(".{}", number))
+}
+
+/// Write `contents` to `path` through a synced temporary file, so the file is never partly written
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let tmp_path = with_suffix(path, ".tmp");
+    let mut tmp = File::create(&tmp_path).map_err(|e| Error::io(&tmp_path, e))?;
+    tmp.write_all(contents)
+        .and_then(|_| tmp.sync_all())
+        .map_err(|e| Error::io(&tmp_path, e))?;
+    fs::rename(&tmp_path, path).map_err(|e| Error::io(path, e))?;
+    // Persist the rename itself; not every platform allows syncing a directory
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        if let Ok(dir) = File::open(dir) {
+            dir.sync_all().ok();
+        }
+    }
+    Ok(())
+}
+
+/// Shift existing backups along and copy the current file to backup 1, keeping `count` backups
+pub fn rotate(path: &Path, count: usize) -> Result<(), Error> {
+    if count == 0 || !path.is_file() {
+        return Ok(());
+    }
+    let oldest = backup_path(path, count);
+    if oldest.is_file() {
+        fs::remove_file(&oldest).map_err(|e| Error::io(&oldest, e))?;
+    }
+    for n in (1..count).rev() {
+        let from = backup_path(path, n);
+        if from.is_file() {
+            let to = backup_path(path, n + 1);
+            fs::rename(&from, &to).map_err(|e| Error::io(&from, e))?;
+        }
+    }
+    let first = backup_path(path, 1);
+    fs::copy(path, &first).map_err(|e| Error::io(&first, e))?;
+    Ok(())
+}
+
+/// Existing backups of `path`, most recent first
+pub fn list(path: &Path) -> Vec<Backup> {
+    (1..)
+        .map(|number| (number, backup_path(path, number)))
+        .take_while(|(_, path)| path.is_file())
+        .map(|(number, path)| {
+            let modified = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Local>::from);
+            Backup {
+                number,
+                path,
+                modified,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_backups() {
+        let dir = std::env::temp_dir().join(format!("booktop-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bookcase.booktop.yaml");
+
+        for version in 1..=4 {
+            rotate(&path, 2).unwrap();
+            write_atomic(&path, format!("version {}", version).as_bytes()).unwrap();
+        }
+
+        let read = |p: &Path| fs::read_to_string(p).unwrap();
+        assert_eq!(read(&path), "version 4");
+        let backups = list(&path);
+        assert_eq!(backups.len(), 2);
+        assert_eq!(read(&backups[0].path), "version 3");
+        assert_eq!(read(&backups[1].path), "version 2");
+        assert!(!with_suffix(&path, ".tmp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
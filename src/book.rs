@@ -1,8 +1,15 @@
+use crate::author;
+use crate::field::FieldValue;
+use crate::isbn::Isbn;
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Eq, PartialEq, Debug, Clone, Default, Hash, Deserialize, Serialize)]
 pub enum Read {
     Read,
@@ -35,79 +42,1019 @@ impl Read {
             Read::Stopped => 'S', //'🔖',
         }
     }
+    /// How far through reading a book the state is
+    fn advancement(&self) -> u8 {
+        match self {
+            Read::Unread => 0,
+            Read::Stopped => 1,
+            Read::Reading => 2,
+            Read::Read => 3,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash, Ord, PartialOrd, Deserialize, Serialize)]
+pub enum Format {
+    Physical,
+    Ebook,
+    Audiobook,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Physical => write!(f, "Physical"),
+            Format::Ebook => write!(f, "Ebook"),
+            Format::Audiobook => write!(f, "Audiobook"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::all()
+            .into_iter()
+            .find(|f| f.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("{} is not a format (physical, ebook, audiobook)", s))
+    }
+}
+
+impl Format {
+    pub const fn all() -> [Format; 3] {
+        [Format::Physical, Format::Ebook, Format::Audiobook]
+    }
+    pub fn symbol(&self) -> char {
+        match self {
+            Format::Physical => 'P',  //'📗',
+            Format::Ebook => 'E',     //'📱',
+            Format::Audiobook => 'A', //'🎧',
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash, Deserialize, Serialize)]
+pub enum Ownership {
+    Owned,
+    Ordered,
+    Borrowed,
+    Library,
+    Wishlist,
+}
+
+impl fmt::Display for Ownership {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ownership::Owned => write!(f, "Owned"),
+            Ownership::Ordered => write!(f, "Ordered"),
+            Ownership::Borrowed => write!(f, "Borrowed"),
+            Ownership::Library => write!(f, "Library"),
+            Ownership::Wishlist => write!(f, "Wishlist"),
+        }
+    }
+}
+
+impl FromStr for Ownership {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ownership::all()
+            .into_iter()
+            .find(|o| o.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "{} is not an ownership (owned, ordered, borrowed, library, wishlist)",
+                    s
+                )
+            })
+    }
+}
+
+impl Ownership {
+    pub const fn all() -> [Ownership; 5] {
+        [
+            Ownership::Owned,
+            Ownership::Ordered,
+            Ownership::Borrowed,
+            Ownership::Library,
+            Ownership::Wishlist,
+        ]
+    }
+    pub fn symbol(&self) -> char {
+        match self {
+            Ownership::Owned => 'O',    //'🏠',
+            Ownership::Ordered => 'o',  //'📦',
+            Ownership::Borrowed => 'B', //'🤝',
+            Ownership::Library => 'L',  //'🏛',
+            Ownership::Wishlist => 'W', //'⭐',
+        }
+    }
+}
+
+/// Days a loan without a due date runs before it is overdue
+pub const DEFAULT_LOAN_DAYS: i64 = 28;
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Loan {
+    pub borrower: String,
+    pub lent: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub returned: Option<NaiveDate>,
+}
+
+impl Loan {
+    pub fn is_out(&self) -> bool {
+        self.returned.is_none()
+    }
+    pub fn due_date(&self) -> NaiveDate {
+        self.due
+            .unwrap_or(self.lent + chrono::Duration::days(DEFAULT_LOAN_DAYS))
+    }
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        self.is_out() && self.due_date() < today
+    }
+}
+
+impl fmt::Display for Loan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} since {}", self.borrower, self.lent)?;
+        if let Some(returned) = self.returned {
+            write!(f, ", returned {}", returned)?;
+        } else if let Some(due) = self.due {
+            write!(f, ", due {}", due)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum NoteKind {
+    #[default]
+    Note,
+    Quote,
+}
+
+impl fmt::Display for NoteKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NoteKind::Note => write!(f, "Note"),
+            NoteKind::Quote => write!(f, "Quote"),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub at: DateTime<Utc>,
+    #[serde(default)]
+    pub kind: NoteKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    pub text: String,
+}
+
+impl Note {
+    /// Date, kind and page of the note, without the text
+    pub fn heading(&self) -> String {
+        let date = self.at.with_timezone(&Local).format("%Y-%m-%d");
+        match self.page {
+            Some(page) => format!("{} {} p. {}", date, self.kind, page),
+            None => format!("{} {}", date, self.kind),
+        }
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.heading(), self.text)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct ReadChange {
+    pub read: Read,
+    pub at: DateTime<Utc>,
+}
+
+impl fmt::Display for ReadChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            self.read
+        )
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum Outcome {
+    Finished,
+    Abandoned,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Finished => write!(f, "Finished"),
+            Outcome::Abandoned => write!(f, "Abandoned"),
+        }
+    }
 }
 
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub start: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
+}
+
+impl Session {
+    fn new(start: DateTime<Utc>) -> Session {
+        Session {
+            start,
+            end: None,
+            outcome: None,
+        }
+    }
+    pub fn is_open(&self) -> bool {
+        self.end.is_none()
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let date = |d: &DateTime<Utc>| d.with_timezone(&Local).format("%Y-%m-%d").to_string();
+        match (&self.end, &self.outcome) {
+            (Some(end), Some(outcome)) => {
+                write!(f, "{} to {} {}", date(&self.start), date(end), outcome)
+            }
+            (Some(end), None) => write!(f, "{} to {}", date(&self.start), date(end)),
+            (None, _) => write!(f, "{} to now", date(&self.start)),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Progress {
+    Page(u32),
+    Percent(u8),
+}
+
+impl Progress {
+    /// Fraction of the book read, if it can be known
+    pub fn fraction(&self, pages: Option<u32>) -> Option<f64> {
+        match (self, pages) {
+            (Progress::Percent(p), _) => Some(f64::from(*p) / 100.0),
+            (Progress::Page(_), Some(0)) => None,
+            (Progress::Page(p), Some(n)) => Some((f64::from(*p) / f64::from(n)).min(1.0)),
+            (Progress::Page(_), None) => None,
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Progress::Page(p) => write!(f, "{}", p),
+            Progress::Percent(p) => write!(f, "{}%", p),
+        }
+    }
+}
+
+impl FromStr for Progress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_suffix('%') {
+            Some(p) => match p.trim().parse::<u8>() {
+                Ok(p) if p <= 100 => Ok(Progress::Percent(p)),
+                _ => Err(format!("{} is not a percentage between 0% and 100%", s)),
+            },
+            None => s
+                .parse::<u32>()
+                .map(Progress::Page)
+                .map_err(|_| format!("{} is neither a page number nor a percentage", s)),
+        }
+    }
+}
+
+impl Serialize for Progress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Progress::Page(p) => serializer.serialize_u32(*p),
+            Progress::Percent(_) => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Progress {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Page(u32),
+            Text(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Page(p) => Ok(Progress::Page(p)),
+            Repr::Text(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Hash, Deserialize, Serialize)]
+pub enum Role {
+    Author,
+    Editor,
+    Translator,
+    Illustrator,
+    Narrator,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Author => write!(f, "Author"),
+            Role::Editor => write!(f, "Editor"),
+            Role::Translator => write!(f, "Translator"),
+            Role::Illustrator => write!(f, "Illustrator"),
+            Role::Narrator => write!(f, "Narrator"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "author" => Ok(Role::Author),
+            "editor" => Ok(Role::Editor),
+            "translator" => Ok(Role::Translator),
+            "illustrator" => Ok(Role::Illustrator),
+            "narrator" => Ok(Role::Narrator),
+            _ => Err(format!("{} is not a contributor role", s.trim())),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Hash, Deserialize, Serialize)]
+#[serde(from = "ContributorRepr", into = "ContributorRepr")]
+pub struct Contributor {
+    pub name: String,
+    pub role: Option<Role>,
+}
+
+/// Contributors without a role are stored as a plain name
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ContributorRepr {
+    Name(String),
+    Full {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        role: Option<Role>,
+    },
+}
+
+impl From<ContributorRepr> for Contributor {
+    fn from(repr: ContributorRepr) -> Self {
+        match repr {
+            ContributorRepr::Name(name) => Contributor { name, role: None },
+            ContributorRepr::Full { name, role } => Contributor { name, role },
+        }
+    }
+}
+
+impl From<Contributor> for ContributorRepr {
+    fn from(contributor: Contributor) -> Self {
+        match contributor.role {
+            None => ContributorRepr::Name(contributor.name),
+            role => ContributorRepr::Full {
+                name: contributor.name,
+                role,
+            },
+        }
+    }
+}
+
+impl Contributor {
+    /// Whether the contributor counts as an author rather than e.g. a translator
+    pub fn is_author(&self) -> bool {
+        matches!(self.role, None | Some(Role::Author))
+    }
+}
+
+impl From<&str> for Contributor {
+    fn from(name: &str) -> Self {
+        Contributor {
+            name: name.to_string(),
+            role: None,
+        }
+    }
+}
+
+impl fmt::Display for Contributor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.role {
+            Some(role) => write!(f, "{} ({})", self.name, role),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Parses `Name` or `Name (Role)`
+impl FromStr for Contributor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, role) = match s.strip_suffix(')').and_then(|s| s.rsplit_once('(')) {
+            Some((name, role)) => (name.trim(), Some(role.parse()?)),
+            None => (s, None),
+        };
+        match name.is_empty() {
+            true => Err("Contributor name is empty".to_string()),
+            false => Ok(Contributor {
+                name: name.to_string(),
+                role,
+            }),
+        }
+    }
+}
+
+/// Accepts either a single contributor, as in older files, or a list
+fn deserialize_contributors<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Contributor>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        One(Contributor),
+        Many(Vec<Contributor>),
+    }
+    match Repr::deserialize(deserializer)? {
+        Repr::One(c) => Ok(vec![c]),
+        Repr::Many(cs) => Ok(cs),
+    }
+}
+
+/// Star rating, stored in half stars
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Ord, PartialOrd)]
+pub struct Rating(u8);
+
+impl Rating {
+    pub const MAX_HALF_STARS: u8 = 10;
+
+    pub fn from_half_stars(half_stars: u8) -> Option<Rating> {
+        (1..=Rating::MAX_HALF_STARS)
+            .contains(&half_stars)
+            .then_some(Rating(half_stars))
+    }
+    pub fn half_stars(&self) -> u8 {
+        self.0
+    }
+    pub fn stars(&self) -> f64 {
+        f64::from(self.0) / 2.0
+    }
+    pub fn symbols(&self) -> String {
+        let half = match self.0 % 2 {
+            1 => "½",
+            _ => "",
+        };
+        format!("{}{}", "*".repeat(usize::from(self.0 / 2)), half)
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/5", self.stars())
+    }
+}
+
+impl FromStr for Rating {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stars: f64 = s
+            .trim()
+            .trim_end_matches("/5")
+            .parse()
+            .map_err(|_| format!("{} is not a number of stars", s))?;
+        let half_stars = stars * 2.0;
+        match half_stars.fract() == 0.0 && (1.0..=10.0).contains(&half_stars) {
+            true => Ok(Rating(half_stars as u8)),
+            false => Err(format!("{} is not a rating in half stars from 0.5 to 5", s)),
+        }
+    }
+}
+
+impl Serialize for Rating {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.stars())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rating {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stars = f64::deserialize(deserializer)?;
+        stars.to_string().parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Position in a series, allowing for fractional volumes such as a novella at 2.5
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Volume(f64);
+
+impl PartialEq for Volume {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Volume {}
+
+impl PartialOrd for Volume {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Volume {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl TryFrom<f64> for Volume {
+    type Error = String;
+
+    fn try_from(v: f64) -> Result<Self, Self::Error> {
+        match v.is_finite() && v >= 0.0 {
+            true => Ok(Volume(v)),
+            false => Err(format!("{} is not a volume number", v)),
+        }
+    }
+}
+
+impl From<Volume> for f64 {
+    fn from(v: Volume) -> f64 {
+        v.0
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Volume {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse::<f64>()
+            .map_err(|_| format!("{} is not a volume number", s))
+            .and_then(Volume::try_from)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Series {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<Volume>,
+}
+
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.volume {
+            Some(volume) => write!(f, "{} #{}", self.name, volume),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub enum Sorting {
     #[default]
     Title,
     Author,
+    Rating,
+    Series,
+    /// By a user-defined field, books without the field last
+    Field(String),
+}
+
+/// Parses `title`, `author`, `rating`, `series` or `field:<name>`
+impl FromStr for Sorting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "title" => Ok(Sorting::Title),
+            "author" => Ok(Sorting::Author),
+            "rating" => Ok(Sorting::Rating),
+            "series" => Ok(Sorting::Series),
+            // Field names keep their case, but the prefix does not
+            _ => match s.trim().split_once(':') {
+                Some((prefix, key)) if prefix.eq_ignore_ascii_case("field") && !key.is_empty() => {
+                    Ok(Sorting::Field(key.to_string()))
+                }
+                _ => Err(format!(
+                    "{} is not a sorting (title, author, rating, series, field:<name>)",
+                    s
+                )),
+            },
+        }
+    }
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct Book {
+    /// Stable identifier, unlike the book's number in the bookcase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<Uuid>,
     pub title: String,
-    pub author: String,
+    #[serde(alias = "author", deserialize_with = "deserialize_contributors")]
+    pub authors: Vec<Contributor>,
     #[serde(default)]
     pub read: Read,
     #[serde(default)]
     pub tags: HashSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub shelves: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<ReadChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sessions: Vec<Session>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<Progress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<Rating>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<Series>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isbn: Option<Isbn>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, FieldValue>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub formats: BTreeSet<Format>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ownership: Option<Ownership>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub loans: Vec<Loan>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
+    /// Bookcase the book was loaded from, when several are combined
+    #[serde(skip)]
+    pub source: Option<String>,
 }
 
 impl Book {
-    pub fn new(title: String, author: String) -> Book {
+    pub fn new(title: String, authors: Vec<Contributor>) -> Book {
         Book {
+            uuid: Some(Uuid::new_v4()),
             title,
-            author,
+            authors,
             read: Read::Unread,
             tags: HashSet::new(),
+            shelves: BTreeSet::new(),
+            history: Vec::new(),
+            sessions: Vec::new(),
+            pages: None,
+            progress: None,
+            rating: None,
+            review: None,
+            series: None,
+            isbn: None,
+            publisher: None,
+            year: None,
+            language: None,
+            edition: None,
+            fields: BTreeMap::new(),
+            formats: BTreeSet::new(),
+            ownership: None,
+            loans: Vec::new(),
+            notes: Vec::new(),
+            source: None,
+        }
+    }
+    fn set_read(&mut self, read: Read, at: DateTime<Utc>) {
+        if self.read != read {
+            self.history.push(ReadChange {
+                read: read.clone(),
+                at,
+            });
+            self.read = read
+        }
+    }
+    fn open_session(&mut self, at: DateTime<Utc>) {
+        if self.current_session().is_none() {
+            self.sessions.push(Session::new(at));
+            self.progress = None;
+        }
+    }
+    fn close_session(&mut self, outcome: Outcome, at: DateTime<Utc>) {
+        self.open_session(at);
+        if let Some(session) = self.sessions.last_mut() {
+            session.end = Some(at);
+            session.outcome = Some(outcome);
         }
     }
     pub fn start(&mut self) {
-        self.read = Read::Reading
+        let now = Utc::now();
+        self.open_session(now);
+        self.set_read(Read::Reading, now)
     }
     pub fn finish(&mut self) {
-        self.read = Read::Read
+        let now = Utc::now();
+        self.close_session(Outcome::Finished, now);
+        self.set_read(Read::Read, now)
     }
     pub fn stop(&mut self) {
-        self.read = Read::Stopped
+        let now = Utc::now();
+        self.open_session(now);
+        self.set_read(Read::Stopped, now)
     }
     pub fn reset(&mut self) {
-        self.read = Read::Unread
+        let now = Utc::now();
+        if self.current_session().is_some() {
+            self.close_session(Outcome::Abandoned, now);
+        }
+        self.set_read(Read::Unread, now)
+    }
+    /// Combine a duplicate entry for the same book into this one, keeping the most advanced
+    /// read state and filling in anything missing
+    pub fn absorb(&mut self, other: Book) {
+        // Contributors named differently, as "Austen, Jane" and "Jane Austen", are the same
+        for author in other.authors {
+            if !self
+                .authors
+                .iter()
+                .any(|a| a.role == author.role && author::same_name(&a.name, &author.name))
+            {
+                self.authors.push(author)
+            }
+        }
+        if other.read.advancement() > self.read.advancement() {
+            self.read = other.read;
+            self.progress = other.progress.or(self.progress);
+        }
+        self.tags.extend(other.tags);
+        self.shelves.extend(other.shelves);
+        self.formats.extend(other.formats);
+        self.history.extend(other.history);
+        self.history.sort_by_key(|c| c.at);
+        self.history.dedup();
+        self.sessions.extend(other.sessions);
+        self.sessions.sort_by_key(|s| s.start);
+        self.sessions.dedup();
+        self.loans.extend(other.loans);
+        self.loans.sort_by_key(|l| l.lent);
+        self.notes.extend(other.notes);
+        self.notes.sort_by_key(|n| n.at);
+        self.notes.dedup();
+        for (name, value) in other.fields {
+            self.fields.entry(name).or_insert(value);
+        }
+        self.pages = self.pages.or(other.pages);
+        self.rating = self.rating.or(other.rating);
+        self.review = self.review.take().or(other.review);
+        self.series = self.series.take().or(other.series);
+        self.isbn = self.isbn.take().or(other.isbn);
+        self.publisher = self.publisher.take().or(other.publisher);
+        self.year = self.year.or(other.year);
+        self.language = self.language.take().or(other.language);
+        self.edition = self.edition.take().or(other.edition);
+        self.ownership = self.ownership.or(other.ownership);
+    }
+    pub fn set_progress(&mut self, progress: Progress) {
+        if self.read != Read::Reading {
+            self.start()
+        }
+        self.progress = Some(progress)
+    }
+    /// Fraction of the book read in the current session
+    pub fn progress_fraction(&self) -> Option<f64> {
+        self.progress.and_then(|p| p.fraction(self.pages))
+    }
+    pub fn contributor_names(&self) -> impl Iterator<Item = &str> {
+        self.authors.iter().map(|c| c.name.as_str())
+    }
+    /// Names of the authors, leaving out other contributors where there are authors
+    pub fn author_names(&self) -> impl Iterator<Item = &str> {
+        let any_authors = self.authors.iter().any(|c| c.is_author());
+        self.authors
+            .iter()
+            .filter(move |c| !any_authors || c.is_author())
+            .map(|c| c.name.as_str())
+    }
+    pub fn authors_string(&self) -> String {
+        self.authors
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+    /// Bibliographic details that have been filled in, for display
+    pub fn details(&self) -> Vec<String> {
+        let mut details = Vec::new();
+        if let Some(isbn) = &self.isbn {
+            details.push(format!("ISBN {}", isbn));
+        }
+        if let Some(publisher) = &self.publisher {
+            details.push(publisher.clone());
+        }
+        if let Some(year) = &self.year {
+            details.push(year.to_string());
+        }
+        if let Some(edition) = &self.edition {
+            details.push(format!("{} edition", edition));
+        }
+        if let Some(language) = &self.language {
+            details.push(language.clone());
+        }
+        details
+    }
+    pub fn formats_string(&self) -> String {
+        self.formats
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    pub fn current_loan(&self) -> Option<&Loan> {
+        self.loans.last().filter(|l| l.is_out())
+    }
+    /// Lend the book, returning false if it is already lent out
+    pub fn lend(&mut self, borrower: String, due: Option<NaiveDate>) -> bool {
+        if self.current_loan().is_some() {
+            return false;
+        }
+        self.loans.push(Loan {
+            borrower,
+            lent: Local::now().date_naive(),
+            due,
+            returned: None,
+        });
+        true
+    }
+    /// Return the book, returning false if it was not lent out
+    pub fn return_loan(&mut self) -> bool {
+        match self.loans.last_mut().filter(|l| l.is_out()) {
+            Some(loan) => {
+                loan.returned = Some(Local::now().date_naive());
+                true
+            }
+            None => false,
+        }
+    }
+    /// Add a note, returning false if there is no text
+    pub fn add_note(&mut self, kind: NoteKind, text: &str, page: Option<u32>) -> bool {
+        let text = text.trim();
+        if text.is_empty() {
+            return false;
+        }
+        self.notes.push(Note {
+            at: Utc::now(),
+            kind,
+            page,
+            text: text.to_string(),
+        });
+        true
+    }
+    pub fn quotes(&self) -> impl Iterator<Item = &Note> {
+        self.notes.iter().filter(|n| n.kind == NoteKind::Quote)
+    }
+    pub fn set_review(&mut self, review: &str) {
+        let review = review.trim();
+        self.review = (!review.is_empty()).then(|| review.to_string())
+    }
+    pub fn current_session(&self) -> Option<&Session> {
+        self.sessions.last().filter(|s| s.is_open())
+    }
+    pub fn sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+    /// Number of finished reads, counting a book marked read before sessions were tracked once
+    pub fn read_count(&self) -> usize {
+        let finished = self
+            .sessions
+            .iter()
+            .filter(|s| s.outcome == Some(Outcome::Finished))
+            .count();
+        match (finished, &self.read) {
+            (0, Read::Read) => 1,
+            (n, _) => n,
+        }
     }
     pub fn read_state(&self) -> &Read {
         &self.read
     }
+    pub fn history(&self) -> &[ReadChange] {
+        &self.history
+    }
+    #[allow(dead_code)]
     pub fn tag(&mut self, tag: &str) -> bool {
         self.tags.insert(tag.to_string())
     }
+    #[allow(dead_code)]
     pub fn untag(&mut self, tag: &str) -> bool {
         self.tags.remove(tag)
     }
     pub fn contains_tag(&self, tag: &str) -> bool {
         self.tags.contains(tag)
     }
+    pub fn is_on_shelf(&self, shelf: &str) -> bool {
+        self.shelves.contains(shelf)
+    }
     pub fn cmp_by(&self, other: &Self, sorting: &Sorting) -> Ordering {
         match sorting {
             Sorting::Title => self.title.cmp(&other.title),
-            Sorting::Author => self.author.cmp(&other.author),
+            Sorting::Author => self
+                .author_names()
+                .map(author::sort_name)
+                .cmp(other.author_names().map(author::sort_name)),
+            // Best rated first, unrated last
+            Sorting::Rating => other.rating.cmp(&self.rating),
+            // Series in volume order, books outside a series last
+            Sorting::Series => match (&self.series, &other.series) {
+                (Some(s), Some(o)) => s.name.cmp(&o.name).then(s.volume.cmp(&o.volume)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => self.title.cmp(&other.title),
+            },
+            Sorting::Field(key) => match (self.fields.get(key), other.fields.get(key)) {
+                (Some(s), Some(o)) => s.cmp(o),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => self.title.cmp(&other.title),
+            },
         }
     }
 }
 
 impl fmt::Display for Book {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}---{:?} ({})", self.title, self.author, self.read)
+        write!(
+            f,
+            "{}---{:?} ({})",
+            self.title,
+            self.authors_string(),
+            self.read
+        )
     }
 }
 
 impl Default for Book {
     fn default() -> Self {
         Book {
+            uuid: None,
             title: "Title Unknown".to_string(),
-            author: "Author Unknown".to_string(),
+            authors: vec![Contributor::from("Author Unknown")],
             read: Read::default(),
             tags: HashSet::default(),
+            shelves: BTreeSet::default(),
+            history: Vec::default(),
+            sessions: Vec::default(),
+            pages: None,
+            progress: None,
+            rating: None,
+            review: None,
+            series: None,
+            isbn: None,
+            publisher: None,
+            year: None,
+            language: None,
+            edition: None,
+            fields: BTreeMap::new(),
+            formats: BTreeSet::new(),
+            ownership: None,
+            loans: Vec::new(),
+            notes: Vec::new(),
+            source: None,
         }
     }
 }
@@ -115,14 +1062,33 @@ impl Default for Book {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_yaml;
 
     fn test_book() -> Book {
         Book {
+            uuid: None,
             title: "Titular Title".to_string(),
-            author: "Authoritative Author".to_string(),
+            authors: vec![Contributor::from("Authoritative Author")],
             read: Read::Unread,
             tags: HashSet::new(),
+            shelves: BTreeSet::new(),
+            history: Vec::new(),
+            sessions: Vec::new(),
+            pages: None,
+            progress: None,
+            rating: None,
+            review: None,
+            series: None,
+            isbn: None,
+            publisher: None,
+            year: None,
+            language: None,
+            edition: None,
+            fields: BTreeMap::new(),
+            formats: BTreeSet::new(),
+            ownership: None,
+            loans: Vec::new(),
+            notes: Vec::new(),
+            source: None,
         }
     }
 
@@ -132,7 +1098,7 @@ mod tests {
         let s = serde_yaml::to_string(&b).unwrap();
         assert_eq!(
             s,
-            "title: Titular Title\nauthor: Authoritative Author\nread: Unread\ntags: []\n"
+            "title: Titular Title\nauthors:\n- Authoritative Author\nread: Unread\ntags: []\n"
                 .to_string()
         );
     }
@@ -150,5 +1116,278 @@ mod tests {
         )
         .unwrap();
         assert_eq!(r, b);
+
+        let r: Book = serde_yaml::from_str(
+            "title: Titular Title\nauthors:\n- Authoritative Author\nread: Unread\n",
+        )
+        .unwrap();
+        assert_eq!(r, b);
+    }
+
+    #[test]
+    fn contributors() {
+        let mut b = test_book();
+        b.authors
+            .push("Trusty Translator (translator)".parse().unwrap());
+        assert_eq!(
+            b.authors[1],
+            Contributor {
+                name: "Trusty Translator".to_string(),
+                role: Some(Role::Translator)
+            }
+        );
+        assert!("Someone (hanger-on)".parse::<Contributor>().is_err());
+        assert_eq!(
+            b.author_names().collect::<Vec<_>>(),
+            vec!["Authoritative Author"]
+        );
+        assert_eq!(
+            b.authors_string(),
+            "Authoritative Author; Trusty Translator (Translator)"
+        );
+
+        let s = serde_yaml::to_string(&b).unwrap();
+        assert!(s.contains(
+            "authors:\n- Authoritative Author\n- name: Trusty Translator\n  role: Translator\n"
+        ));
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
+    }
+
+    #[test]
+    fn history() {
+        let mut b = test_book();
+        b.start();
+        b.start();
+        b.stop();
+        b.start();
+        b.finish();
+        assert_eq!(
+            b.history().iter().map(|c| &c.read).collect::<Vec<_>>(),
+            vec![&Read::Reading, &Read::Stopped, &Read::Reading, &Read::Read]
+        );
+        assert!(b.history().windows(2).all(|w| w[0].at <= w[1].at));
+
+        let s = serde_yaml::to_string(&b).unwrap();
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
+    }
+
+    #[test]
+    fn sessions() {
+        let mut b = test_book();
+        assert_eq!(b.read_count(), 0);
+
+        b.start();
+        assert!(b.current_session().is_some());
+        b.stop();
+        b.start();
+        b.finish();
+        assert_eq!(b.sessions().len(), 1);
+        assert_eq!(b.current_session(), None);
+        assert_eq!(b.read_count(), 1);
+
+        // Starting a finished book opens a new session
+        b.start();
+        assert_eq!(b.sessions().len(), 2);
+        b.finish();
+        assert_eq!(b.read_count(), 2);
+
+        // Finishing a finished book records another read
+        b.finish();
+        assert_eq!(b.read_count(), 3);
+
+        b.start();
+        b.reset();
+        assert_eq!(b.sessions()[3].outcome, Some(Outcome::Abandoned));
+        assert_eq!(b.read_count(), 3);
+    }
+
+    #[test]
+    fn read_count_without_sessions() {
+        let r: Book = serde_yaml::from_str(
+            "title: Titular Title\nauthor: Authoritative Author\nread: Read\n",
+        )
+        .unwrap();
+        assert_eq!(r.read_count(), 1);
+    }
+
+    #[test]
+    fn progress() {
+        assert_eq!("42".parse(), Ok(Progress::Page(42)));
+        assert_eq!("42%".parse(), Ok(Progress::Percent(42)));
+        assert!("142%".parse::<Progress>().is_err());
+        assert!("page".parse::<Progress>().is_err());
+
+        let mut b = test_book();
+        b.set_progress(Progress::Page(50));
+        assert_eq!(b.read_state(), &Read::Reading);
+        assert_eq!(b.progress_fraction(), None);
+        b.pages = Some(200);
+        assert_eq!(b.progress_fraction(), Some(0.25));
+        b.set_progress(Progress::Percent(40));
+        assert_eq!(b.progress_fraction(), Some(0.4));
+
+        let s = serde_yaml::to_string(&b).unwrap();
+        assert!(s.contains("progress: 40%"));
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
+
+        // A new session starts without progress
+        b.finish();
+        b.start();
+        assert_eq!(b.progress, None);
+    }
+
+    #[test]
+    fn rating() {
+        assert_eq!("3.5".parse(), Ok(Rating(7)));
+        assert_eq!("5/5".parse(), Ok(Rating(10)));
+        assert!("0".parse::<Rating>().is_err());
+        assert!("3.3".parse::<Rating>().is_err());
+        assert!("5.5".parse::<Rating>().is_err());
+        assert_eq!(Rating(7).symbols(), "***½");
+
+        let mut b = test_book();
+        b.rating = Some(Rating(7));
+        b.review = Some("Rather good".to_string());
+        let s = serde_yaml::to_string(&b).unwrap();
+        assert!(s.contains("rating: 3.5\n"));
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
+
+        let mut unrated = test_book();
+        unrated.title = "Unrated".to_string();
+        assert_eq!(b.cmp_by(&unrated, &Sorting::Rating), Ordering::Less);
+    }
+
+    #[test]
+    fn series() {
+        assert_eq!("2.5".parse::<Volume>().map(f64::from), Ok(2.5));
+        assert!("-1".parse::<Volume>().is_err());
+        assert!("NaN".parse::<Volume>().is_err());
+
+        let in_series = |title: &str, volume: &str| Book {
+            title: title.to_string(),
+            series: Some(Series {
+                name: "Serial".to_string(),
+                volume: Some(volume.parse().unwrap()),
+            }),
+            ..test_book()
+        };
+        let b2 = in_series("Second", "2");
+        let b25 = in_series("Novella", "2.5");
+        let b10 = in_series("Tenth", "10");
+        assert_eq!(b2.cmp_by(&b25, &Sorting::Series), Ordering::Less);
+        assert_eq!(b10.cmp_by(&b25, &Sorting::Series), Ordering::Greater);
+        assert_eq!(b10.cmp_by(&test_book(), &Sorting::Series), Ordering::Less);
+        assert_eq!(b25.series.as_ref().unwrap().to_string(), "Serial #2.5");
+
+        let s = serde_yaml::to_string(&b25).unwrap();
+        assert!(s.contains("series:\n  name: Serial\n  volume: 2.5\n"));
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b25);
+    }
+
+    #[test]
+    fn details() {
+        let mut b = test_book();
+        assert!(b.details().is_empty());
+        b.isbn = Some("0141439564".parse().unwrap());
+        b.year = Some(1861);
+        b.edition = Some("2nd".to_string());
+        assert_eq!(
+            b.details(),
+            vec!["ISBN 9780141439563", "1861", "2nd edition"]
+        );
+
+        let s = serde_yaml::to_string(&b).unwrap();
+        assert!(s.contains("isbn: '9780141439563'\nyear: 1861\nedition: 2nd\n"));
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
+    }
+
+    #[test]
+    fn fields() {
+        let mut b = test_book();
+        b.fields
+            .insert("price".to_string(), FieldValue::Number(12.5));
+        b.fields
+            .insert("shelf".to_string(), FieldValue::String("Attic".to_string()));
+        let s = serde_yaml::to_string(&b).unwrap();
+        assert!(s.contains("fields:\n  price: 12.5\n  shelf: Attic\n"));
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
+
+        let mut cheap = test_book();
+        cheap
+            .fields
+            .insert("price".to_string(), FieldValue::Number(2.0));
+        let sorting: Sorting = "field:price".parse().unwrap();
+        assert_eq!("Field:price".parse(), Ok(sorting.clone()));
+        assert_eq!(cheap.cmp_by(&b, &sorting), Ordering::Less);
+        assert_eq!(b.cmp_by(&test_book(), &sorting), Ordering::Less);
+    }
+
+    #[test]
+    fn formats_and_ownership() {
+        assert_eq!("ebook".parse(), Ok(Format::Ebook));
+        assert_eq!("Wishlist".parse(), Ok(Ownership::Wishlist));
+        assert!("scroll".parse::<Format>().is_err());
+
+        let mut b = test_book();
+        b.formats.insert(Format::Audiobook);
+        b.formats.insert(Format::Physical);
+        b.ownership = Some(Ownership::Owned);
+        assert_eq!(b.formats_string(), "Physical, Audiobook");
+
+        let s = serde_yaml::to_string(&b).unwrap();
+        assert!(s.contains("formats:\n- Physical\n- Audiobook\nownership: Owned\n"));
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
+    }
+
+    #[test]
+    fn loans() {
+        let mut b = test_book();
+        assert!(!b.return_loan());
+        assert!(b.lend("Lender".to_string(), None));
+        assert!(!b.lend("Other Lender".to_string(), None));
+        assert_eq!(b.current_loan().unwrap().borrower, "Lender");
+        assert!(b.return_loan());
+        assert_eq!(b.current_loan(), None);
+        assert!(b.lend("Other Lender".to_string(), None));
+        assert_eq!(b.loans.len(), 2);
+
+        let loan = Loan {
+            borrower: "Lender".to_string(),
+            lent: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            due: None,
+            returned: None,
+        };
+        assert!(!loan.is_overdue(NaiveDate::from_ymd_opt(2024, 1, 29).unwrap()));
+        assert!(loan.is_overdue(NaiveDate::from_ymd_opt(2024, 1, 30).unwrap()));
+        let loan = Loan {
+            due: NaiveDate::from_ymd_opt(2024, 1, 10),
+            ..loan
+        };
+        assert!(loan.is_overdue(NaiveDate::from_ymd_opt(2024, 1, 11).unwrap()));
+    }
+
+    #[test]
+    fn notes() {
+        let mut b = test_book();
+        assert!(!b.add_note(NoteKind::Note, " \n", None));
+        assert!(b.add_note(NoteKind::Note, "Slow start\n", None));
+        assert!(b.add_note(NoteKind::Quote, "It was the best of times", Some(1)));
+        assert_eq!(b.notes[0].text, "Slow start");
+        assert_eq!(
+            b.quotes().map(|q| q.page).collect::<Vec<_>>(),
+            vec![Some(1)]
+        );
+
+        let s = serde_yaml::to_string(&b).unwrap();
+        let r: Book = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, b);
     }
 }
@@ -1,37 +1,255 @@
-use crate::book::Book;
+use crate::author::{self, Author};
+use crate::backup;
+use crate::book::{Book, Contributor, Loan, Read, Sorting};
+use crate::error::Error;
+use crate::filter::Filter;
+use crate::journal::{self, Journal};
+use crate::migrate;
 use rand::seq::IteratorRandom;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet};
-use std::fs::File;
+use std::fmt;
+use std::fs;
 use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
 
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Bookcase {
+    /// Schema version of the file format, see [`migrate`]
+    #[serde(default)]
+    pub version: u64,
     pub name: String,
+    /// Shelves in the bookcase, including those without any books
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shelves: Vec<String>,
+    /// Canonical names of authors, with any aliases and sort names
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<Author>,
     pub books: BTreeMap<usize, Book>,
+    /// The file as it was opened, to detect and merge changes made meanwhile
+    #[serde(skip)]
+    origin: Option<Origin>,
+    #[serde(skip)]
+    journal: Journal,
+    /// The bookcase as of the last operation recorded in the journal
+    #[serde(skip)]
+    checkpoint: journal::State,
+    /// Combined from several files, so cannot be saved
+    #[serde(skip)]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Origin {
+    /// Contents of the file
+    contents: String,
+    /// The bookcase as loaded from the file, after migrating
+    loaded: String,
+    /// Whether to save the file even if the books are unchanged, as when writing a migration
+    changed: bool,
+}
+
+/// Reference to a book, by its number in the bookcase or by (a prefix of) its UUID
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BookRef {
+    Number(usize),
+    Uuid(String),
+}
+
+impl FromStr for BookRef {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(n) = s.parse() {
+            return Ok(BookRef::Number(n));
+        }
+        let hex = s.replace('-', "").to_lowercase();
+        match hex.len() >= 4 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            true => Ok(BookRef::Uuid(hex)),
+            false => Err(format!("{} is not a book number or UUID", s)),
+        }
+    }
+}
+
+impl fmt::Display for BookRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookRef::Number(n) => write!(f, "{}", n),
+            BookRef::Uuid(u) => write!(f, "{}", u),
+        }
+    }
 }
 
 impl Bookcase {
     pub fn new() -> Bookcase {
         Bookcase {
+            version: migrate::SCHEMA_VERSION,
             name: "Bookcase".to_string(),
+            shelves: Vec::new(),
+            authors: Vec::new(),
             books: BTreeMap::new(),
+            origin: None,
+            journal: Journal::default(),
+            checkpoint: journal::State::default(),
+            read_only: false,
+        }
+    }
+    /// Combine several bookcases into a read-only view, numbering the books afresh and noting
+    /// the source of each
+    pub fn aggregate(bookcases: Vec<(String, Bookcase)>) -> Bookcase {
+        let mut combined = Bookcase::new();
+        combined.name = bookcases
+            .iter()
+            .map(|(_, b)| b.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" + ");
+        combined.read_only = true;
+        for (source, bookcase) in bookcases {
+            for shelf in bookcase.shelves {
+                if !combined.shelves.contains(&shelf) {
+                    combined.shelves.push(shelf)
+                }
+            }
+            for mut book in bookcase.books.into_values() {
+                book.source = Some(source.clone());
+                combined.books.insert(combined.books.len() + 1, book);
+            }
+        }
+        combined
+    }
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Bookcase, Error> {
+        let contents = fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+        let mut value: serde_yaml::Value =
+            serde_yaml::from_str(&contents).map_err(|e| Error::parse(&path, e))?;
+        let mut bookcase: Bookcase = match migrate::version(&value) {
+            // Deserialise from the text where possible, so errors keep their location
+            migrate::SCHEMA_VERSION => serde_yaml::from_str(&contents),
+            _ => {
+                migrate::migrate(&mut value)?;
+                serde_yaml::from_value(value)
+            }
+        }
+        .map_err(|e| Error::parse(&path, e))?;
+        let loaded = serde_yaml::to_string(&bookcase).map_err(|e| Error::serialise(&path, e))?;
+        bookcase.origin = Some(Origin {
+            contents,
+            loaded,
+            changed: false,
+        });
+        bookcase.journal = Journal::open(&Journal::path(path.as_ref()))?;
+        bookcase.checkpoint = bookcase.state();
+        Ok(bookcase)
+    }
+    /// Save the bookcase atomically if it has changed, keeping `backups` previous versions
+    ///
+    /// Fails with [`Error::Conflict`] if the file has changed since it was opened.
+    pub fn close<P: AsRef<Path>>(&mut self, path: P, backups: usize) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::Validation(
+                "Bookcases combined from several files cannot be saved".to_string(),
+            ));
+        }
+        if let (Some(origin), Ok(current)) = (&self.origin, fs::read_to_string(&path)) {
+            if origin.contents != current {
+                return Err(Error::Conflict(path.as_ref().to_path_buf()));
+            }
+        }
+        let contents = serde_yaml::to_string(self).map_err(|e| Error::serialise(&path, e))?;
+        // Leave the file and its backups alone when nothing changed, even if loading migrated
+        // it. Tags are kept in a set, so compare with the bookcase as loaded rather than the
+        // file, which may order them differently
+        if self
+            .origin
+            .as_ref()
+            .is_some_and(|origin| !origin.changed && origin.loaded == contents)
+        {
+            return Ok(());
+        }
+        // Books from before UUIDs are given one when the file is next written rather than when
+        // it is loaded, so reading a copy elsewhere doesn't give them different ones
+        let contents = match self.assign_uuids() {
+            true => serde_yaml::to_string(self).map_err(|e| Error::serialise(&path, e))?,
+            false => contents,
+        };
+        backup::rotate(path.as_ref(), backups)?;
+        backup::write_atomic(path.as_ref(), contents.as_bytes())?;
+        self.journal.save(&Journal::path(path.as_ref()))
+    }
+    /// Save the file when closed even if the books are unchanged
+    pub fn mark_changed(&mut self) {
+        if let Some(origin) = &mut self.origin {
+            origin.changed = true
         }
     }
-    pub fn open<P: AsRef<Path>>(path: P) -> Bookcase {
-        let _file = File::open(path).expect("Could not open file");
-        serde_yaml::from_reader(_file).expect("Couldn't extract bookcase")
+    /// Give any books without a UUID one, returning whether there were any
+    fn assign_uuids(&mut self) -> bool {
+        let mut assigned = false;
+        for book in self.books.values_mut().filter(|b| b.uuid.is_none()) {
+            book.uuid = Some(Uuid::new_v4());
+            assigned = true;
+        }
+        assigned
+    }
+    /// The parts of the bookcase kept in the journal
+    fn state(&self) -> journal::State {
+        journal::State {
+            books: self.books.clone(),
+            shelves: self.shelves.clone(),
+            authors: self.authors.clone(),
+        }
     }
-    pub fn close<P: AsRef<Path>>(&self, path: P) {
-        let _file = File::create(path).expect("Could not open file");
-        serde_yaml::to_writer(_file, self).expect("Could not write to file");
+    fn set_state(&mut self, state: journal::State) {
+        self.books = state.books;
+        self.shelves = state.shelves;
+        self.authors = state.authors;
+    }
+    /// Record changes since the last operation in the journal, so they can be undone
+    pub fn commit(&mut self, description: &str) -> bool {
+        let state = self.state();
+        let changed = self.journal.record(description, &self.checkpoint, &state);
+        if changed {
+            self.checkpoint = state;
+        }
+        changed
     }
-    pub fn add_book(&mut self, title: String, author: String) {
+    /// Undo the last operation, returning its description
+    pub fn undo(&mut self) -> Option<String> {
+        self.commit("unrecorded changes");
+        let mut state = self.state();
+        let description = self.journal.undo(&mut state)?.description.clone();
+        self.set_state(state);
+        self.checkpoint = self.state();
+        Some(description)
+    }
+    /// Redo the last operation undone, returning its description
+    pub fn redo(&mut self) -> Option<String> {
+        self.commit("unrecorded changes");
+        let mut state = self.state();
+        let description = self.journal.redo(&mut state)?.description.clone();
+        self.set_state(state);
+        self.checkpoint = self.state();
+        Some(description)
+    }
+    /// Replace the contents of the bookcase, as a change to it rather than a different file
+    pub fn replace(&mut self, other: Bookcase) {
+        self.version = other.version;
+        self.name = other.name;
+        self.shelves = other.shelves;
+        self.authors = other.authors;
+        self.books = other.books;
+    }
+    pub fn add_book(&mut self, title: String, authors: Vec<Contributor>) -> usize {
+        self.insert_book(Book::new(title, authors))
+    }
+    /// Add a book under the next free number, using canonical names for its contributors
+    pub fn insert_book(&mut self, mut book: Book) -> usize {
         let key = match self.books.keys().max() {
             Some(max_key) => max_key + 1,
             None => 1,
         };
-        self.books.insert(key, Book::new(title, author));
+        self.apply_author_records(&mut book);
+        self.books.insert(key, book);
+        key
     }
     pub fn get_book(&self, id: &usize) -> Option<&Book> {
         self.books.get(id)
@@ -39,6 +257,33 @@ impl Bookcase {
     pub fn get_mut_book(&mut self, id: usize) -> Option<&mut Book> {
         self.books.get_mut(&id)
     }
+    pub fn try_get_mut_book(&mut self, id: usize) -> Result<&mut Book, Error> {
+        self.get_mut_book(id)
+            .ok_or_else(|| Error::Validation(format!("No book with id {}", id)))
+    }
+    /// The number of the book referred to
+    pub fn resolve(&self, book: &BookRef) -> Result<usize, Error> {
+        let mut matches: Vec<usize> = match book {
+            BookRef::Number(n) => self.books.keys().filter(|k| *k == n).copied().collect(),
+            BookRef::Uuid(prefix) => self
+                .books
+                .iter()
+                .filter(|(_, b)| {
+                    b.uuid
+                        .is_some_and(|u| u.simple().to_string().starts_with(prefix))
+                })
+                .map(|(k, _)| *k)
+                .collect(),
+        };
+        match (matches.pop(), matches.is_empty()) {
+            (Some(id), true) => Ok(id),
+            (Some(_), false) => Err(Error::Validation(format!(
+                "{} matches more than one book",
+                book
+            ))),
+            (None, _) => Err(Error::Validation(format!("No book with id {}", book))),
+        }
+    }
     pub fn get_books(&self) -> impl IntoIterator<Item = (&usize, &Book)> {
         &self.books
     }
@@ -48,10 +293,143 @@ impl Bookcase {
     ) -> impl Iterator<Item = Option<(&'k usize, &'k Book)>> {
         keys.iter().map(|k| self.get_book(k).map(|b| (k, b)))
     }
-    pub fn get_authors(&self) -> Vec<&str> {
-        let mut authors: Vec<&str> = self.books.values().map(|b| b.author.as_str()).collect();
+    /// Canonical names of all contributors, in order of sort name
+    pub fn get_authors(&self) -> Vec<String> {
+        let mut authors: Vec<(String, String)> = self
+            .books
+            .values()
+            .flat_map(|b| b.contributor_names())
+            .map(|n| (self.author_sort_name(n), self.canonical_author(n)))
+            .collect();
+        authors.sort_unstable();
         authors.dedup();
-        authors
+        authors.into_iter().map(|(_, name)| name).collect()
+    }
+    fn author_record(&self, name: &str) -> Option<&Author> {
+        self.authors.iter().find(|a| a.is_called(name))
+    }
+    /// Rename contributors with an author record to the record's name, leaving others as given
+    pub fn apply_author_records(&self, book: &mut Book) {
+        for a in book.authors.iter_mut() {
+            if let Some(author) = self.author_record(&a.name) {
+                a.name = author.name.clone()
+            }
+        }
+    }
+    /// The name an author is known by, following aliases
+    pub fn canonical_author(&self, name: &str) -> String {
+        match self.author_record(name) {
+            Some(a) => a.name.clone(),
+            None => author::display_name(name),
+        }
+    }
+    pub fn author_sort_name(&self, name: &str) -> String {
+        match self.author_record(name) {
+            Some(a) => a.sort_name(),
+            None => author::sort_name(&author::display_name(name)),
+        }
+    }
+    /// Make `others` aliases of the author `name`, renaming them in books; returns the number
+    /// of books changed
+    pub fn merge_authors(&mut self, name: &str, others: &[String]) -> usize {
+        let name = self.canonical_author(name);
+        let mut merged = match self.authors.iter().position(|a| a.name == name) {
+            Some(i) => self.authors.remove(i),
+            None => Author::new(&name),
+        };
+        for other in others {
+            // Aliases of a merged author carry over
+            if let Some(i) = self.authors.iter().position(|a| a.is_called(other)) {
+                let record = self.authors.remove(i);
+                merged.aliases.push(record.name);
+                merged.aliases.extend(record.aliases);
+            }
+            merged.aliases.push(other.clone());
+        }
+        merged.aliases.retain(|a| a != &merged.name);
+        merged.aliases.sort();
+        merged.aliases.dedup();
+
+        let mut changed = 0;
+        for book in self.books.values_mut() {
+            let mut renamed = false;
+            for contributor in book.authors.iter_mut() {
+                if contributor.name != merged.name && merged.is_called(&contributor.name) {
+                    contributor.name = merged.name.clone();
+                    renamed = true;
+                }
+            }
+            if renamed {
+                book.authors.dedup();
+                changed += 1;
+            }
+        }
+        self.authors.push(merged);
+        self.authors.sort_by_key(|a| a.sort_name());
+        changed
+    }
+    pub fn set_author_sort_name(&mut self, name: &str, sort_name: Option<String>) {
+        let name = self.canonical_author(name);
+        match self.authors.iter_mut().find(|a| a.name == name) {
+            Some(a) => a.sort_name = sort_name,
+            None => self.authors.push(Author {
+                sort_name,
+                ..Author::new(&name)
+            }),
+        }
+    }
+    /// Pairs of authors which could be the same person under different names
+    pub fn similar_authors(&self) -> Vec<(String, String)> {
+        let authors = self.get_authors();
+        let mut pairs = Vec::new();
+        for (i, a) in authors.iter().enumerate() {
+            for b in &authors[i + 1..] {
+                if author::could_be(a, b) {
+                    pairs.push((a.clone(), b.clone()))
+                }
+            }
+        }
+        pairs
+    }
+    /// Compare books as [`Book::cmp_by`], using sort names of authors from the bookcase
+    pub fn cmp_books(&self, a: &Book, b: &Book, sorting: &Sorting) -> std::cmp::Ordering {
+        match sorting {
+            Sorting::Author => {
+                let sort_names = |book: &Book| -> Vec<String> {
+                    book.author_names()
+                        .map(|n| self.author_sort_name(n))
+                        .collect()
+                };
+                sort_names(a).cmp(&sort_names(b))
+            }
+            _ => a.cmp_by(b, sorting),
+        }
+    }
+    /// The first unread volume of each series in which a book has been started
+    pub fn next_in_series(&self) -> Vec<(&usize, &Book)> {
+        let mut series: BTreeMap<&str, Vec<(&usize, &Book)>> = BTreeMap::new();
+        for (id, book) in &self.books {
+            if let Some(s) = &book.series {
+                series.entry(s.name.as_str()).or_default().push((id, book));
+            }
+        }
+        series
+            .into_values()
+            .filter(|books| books.iter().any(|(_, b)| b.read_state() != &Read::Unread))
+            .filter_map(|books| {
+                books
+                    .into_iter()
+                    .filter(|(_, b)| b.read_state() == &Read::Unread)
+                    .min_by(|(_, b1), (_, b2)| b1.cmp_by(b2, &Sorting::Series))
+            })
+            .collect()
+    }
+    /// Books currently lent out, with their loans
+    pub fn get_loans(&self) -> Vec<(&usize, &Book, &Loan)> {
+        self.books
+            .iter()
+            .filter_map(|(id, b)| b.current_loan().map(|l| (id, b, l)))
+            .collect()
     }
     pub fn get_tags(&self) -> Vec<String> {
         let mut tags: HashSet<String> = HashSet::new();
@@ -60,16 +438,112 @@ impl Bookcase {
         }
         tags.into_iter().collect()
     }
-    pub fn pick_book(&self) -> (&usize, &Book) {
-        let mut rng = rand::thread_rng();
-        match self.books.iter().choose(&mut rng) {
-            Some(book) => book,
-            None => panic!("No books to pick from"),
+    /// Shelves with the number of books on each, including any books are on but not listed
+    pub fn get_shelves(&self) -> Vec<(&str, usize)> {
+        let mut shelves: Vec<&str> = self.shelves.iter().map(String::as_str).collect();
+        for book in self.books.values() {
+            for shelf in &book.shelves {
+                if !shelves.contains(&shelf.as_str()) {
+                    shelves.push(shelf)
+                }
+            }
+        }
+        shelves
+            .into_iter()
+            .map(|s| (s, self.books.values().filter(|b| b.is_on_shelf(s)).count()))
+            .collect()
+    }
+    pub fn has_shelf(&self, shelf: &str) -> bool {
+        self.get_shelves().iter().any(|(s, _)| *s == shelf)
+    }
+    /// Add an empty shelf, returning false if it already exists
+    pub fn create_shelf(&mut self, shelf: &str) -> bool {
+        if self.has_shelf(shelf) {
+            return false;
+        }
+        self.shelves.push(shelf.to_string());
+        true
+    }
+    /// Remove a shelf, taking any books off it
+    pub fn delete_shelf(&mut self, shelf: &str) -> Result<(), Error> {
+        self.check_shelf(shelf)?;
+        self.shelves.retain(|s| s != shelf);
+        for book in self.books.values_mut() {
+            book.shelves.remove(shelf);
+        }
+        Ok(())
+    }
+    pub fn rename_shelf(&mut self, shelf: &str, new_name: &str) -> Result<(), Error> {
+        self.check_shelf(shelf)?;
+        if self.has_shelf(new_name) {
+            return Err(Error::Validation(format!(
+                "Shelf {} already exists",
+                new_name
+            )));
         }
+        match self.shelves.iter_mut().find(|s| *s == shelf) {
+            Some(s) => *s = new_name.to_string(),
+            None => self.shelves.push(new_name.to_string()),
+        }
+        for book in self.books.values_mut() {
+            if book.shelves.remove(shelf) {
+                book.shelves.insert(new_name.to_string());
+            }
+        }
+        Ok(())
+    }
+    /// Put a book on a shelf, creating the shelf if needed
+    pub fn shelve(&mut self, id: usize, shelf: &str) -> Result<(), Error> {
+        self.try_get_mut_book(id)?.shelves.insert(shelf.to_string());
+        self.create_shelf(shelf);
+        Ok(())
+    }
+    pub fn unshelve(&mut self, id: usize, shelf: &str) -> Result<(), Error> {
+        self.check_shelf(shelf)?;
+        self.try_get_mut_book(id)?.shelves.remove(shelf);
+        Ok(())
+    }
+    pub fn check_shelf(&self, shelf: &str) -> Result<(), Error> {
+        match self.has_shelf(shelf) {
+            true => Ok(()),
+            false => Err(Error::Validation(format!("No shelf named {}", shelf))),
+        }
+    }
+    pub fn pick_book(&self, filter: &Filter) -> Result<(&usize, &Book), Error> {
+        let mut rng = rand::thread_rng();
+        self.books
+            .iter()
+            .filter(|(_, b)| filter.match_book(b))
+            .choose(&mut rng)
+            .ok_or_else(|| Error::Validation("No books to pick from".to_string()))
     }
     pub fn remove_book(&mut self, id: usize) {
         self.books.remove(&id);
     }
+    /// Merge a duplicate entry into the book kept, removing the duplicate
+    pub fn merge_duplicate(&mut self, keep: usize, duplicate: usize) -> Result<(), Error> {
+        if keep == duplicate {
+            return Ok(());
+        }
+        self.try_get_mut_book(keep)?;
+        let duplicate = self
+            .books
+            .remove(&duplicate)
+            .ok_or_else(|| Error::Validation(format!("No book with id {}", duplicate)))?;
+        self.try_get_mut_book(keep)?.absorb(duplicate);
+        Ok(())
+    }
+    /// The bookcase as it was when opened
+    pub fn origin(&self) -> Result<Option<Bookcase>, Error> {
+        match &self.origin {
+            Some(origin) => Ok(Some(serde_yaml::from_str(&origin.loaded)?)),
+            None => Ok(None),
+        }
+    }
+    /// Treat the file as opened when `other` was, once its changes have been merged in
+    pub fn take_origin(&mut self, other: Bookcase) {
+        self.origin = other.origin;
+    }
     pub fn util_renumber(&mut self) {
         let tmp = self.books.split_off(&0);
         for (ind, val) in tmp.into_values().enumerate() {
@@ -81,12 +555,11 @@ impl Bookcase {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
 
     fn test_book1() -> Book {
         Book {
             title: "Titular Title".to_string(),
-            author: "Authoritative Author".to_string(),
+            authors: vec![Contributor::from("Authoritative Author")],
             tags: HashSet::from(["alpha".to_string(), "beta".to_string()]),
             ..Default::default()
         }
@@ -95,7 +568,7 @@ mod tests {
     fn test_book2() -> Book {
         Book {
             title: "Uitular Title".to_string(),
-            author: "Buthoritative Author".to_string(),
+            authors: vec![Contributor::from("Buthoritative Author")],
             tags: HashSet::from(["alpha".to_string(), "beta".to_string()]),
             ..Default::default()
         }
@@ -104,7 +577,7 @@ mod tests {
     fn test_book3() -> Book {
         Book {
             title: "Vitular Title".to_string(),
-            author: "Cuthoritative Author".to_string(),
+            authors: vec![Contributor::from("Cuthoritative Author")],
             ..Default::default()
         }
     }
@@ -116,6 +589,7 @@ mod tests {
         Bookcase {
             name: "Bookcase name".to_string(),
             books: BTreeMap::from([(1, b1), (2, b2), (3, b3)]),
+            ..Bookcase::new()
         }
     }
 
@@ -164,4 +638,257 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn get_authors() {
+        let mut b = test_bookcase();
+        b.add_book(
+            "Wituler Title".to_string(),
+            vec![
+                Contributor::from("Cuthoritative Author"),
+                Contributor::from("Authoritative Author"),
+            ],
+        );
+        assert_eq!(
+            b.get_authors(),
+            vec![
+                "Authoritative Author",
+                "Buthoritative Author",
+                "Cuthoritative Author"
+            ]
+        );
+    }
+
+    #[test]
+    fn next_in_series() {
+        use crate::book::Series;
+
+        let mut b = test_bookcase();
+        let mut set_series = |id: usize, name: &str, volume: &str| {
+            b.get_mut_book(id).unwrap().series = Some(Series {
+                name: name.to_string(),
+                volume: Some(volume.parse().unwrap()),
+            })
+        };
+        set_series(1, "First", "1");
+        set_series(2, "First", "3");
+        set_series(3, "First", "2.5");
+        assert_eq!(b.next_in_series(), vec![]);
+
+        b.get_mut_book(1).unwrap().finish();
+        assert_eq!(b.next_in_series(), vec![(&3, &b.books[&3])]);
+
+        b.get_mut_book(3).unwrap().start();
+        assert_eq!(b.next_in_series(), vec![(&2, &b.books[&2])]);
+    }
+
+    #[test]
+    fn resolve() {
+        let mut b = test_bookcase();
+        b.get_mut_book(1).unwrap().uuid = Some(Uuid::from_u128(0xa1b2));
+        b.get_mut_book(2).unwrap().uuid = Some(Uuid::from_u128(0xa1b3));
+        let r = |b: &Bookcase, s: &str| b.resolve(&s.parse().unwrap());
+
+        assert_eq!(r(&b, "3").unwrap(), 3);
+        assert!(r(&b, "4").is_err());
+        assert_eq!(r(&b, "00000000-0000-0000-0000-00000000a1b2").unwrap(), 1);
+        assert_eq!(r(&b, "0000000000000000000000000000A1B3").unwrap(), 2);
+        // Prefix shared by both
+        assert!(r(&b, "0000").is_err());
+        assert!("title".parse::<BookRef>().is_err());
+
+        // UUIDs survive renumbering
+        b.remove_book(1);
+        b.util_renumber();
+        assert_eq!(r(&b, "00000000-0000-0000-0000-00000000a1b3").unwrap(), 1);
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut b = test_bookcase();
+        b.checkpoint = b.state();
+
+        b.remove_book(1);
+        assert!(b.commit("remove 1"));
+        b.get_mut_book(2).unwrap().start();
+
+        // Unrecorded changes are recorded before undoing
+        assert_eq!(b.undo().as_deref(), Some("unrecorded changes"));
+        assert_eq!(b.books[&2].read_state(), &Read::Unread);
+        assert_eq!(b.undo().as_deref(), Some("remove 1"));
+        assert_eq!(b.books, test_bookcase().books);
+        assert_eq!(b.undo(), None);
+
+        assert_eq!(b.redo().as_deref(), Some("remove 1"));
+        assert!(!b.books.contains_key(&1));
+
+        b.create_shelf("Attic");
+        assert!(b.commit("shelf create Attic"));
+        assert_eq!(b.undo().as_deref(), Some("shelf create Attic"));
+        assert!(b.shelves.is_empty());
+
+        b.set_author_sort_name("Authoritative Author", Some("Author".to_string()));
+        assert!(b.commit("util authors sort-name"));
+        assert_eq!(b.undo().as_deref(), Some("util authors sort-name"));
+        assert!(b.authors.is_empty());
+        assert!(!b.books.contains_key(&1));
+    }
+
+    #[test]
+    fn shelves() {
+        let mut b = test_bookcase();
+        assert!(b.create_shelf("To buy"));
+        assert!(!b.create_shelf("To buy"));
+        b.shelve(1, "Fiction").unwrap();
+        b.shelve(2, "Fiction").unwrap();
+        b.shelve(2, "Work reading").unwrap();
+        assert!(b.shelve(4, "Fiction").is_err());
+        assert_eq!(
+            b.get_shelves(),
+            vec![("To buy", 0), ("Fiction", 2), ("Work reading", 1)]
+        );
+
+        b.rename_shelf("Fiction", "Novels").unwrap();
+        assert!(b.rename_shelf("Novels", "To buy").is_err());
+        assert!(b.books[&1].is_on_shelf("Novels"));
+        b.unshelve(1, "Novels").unwrap();
+        b.delete_shelf("Work reading").unwrap();
+        assert!(b.delete_shelf("Fiction").is_err());
+        assert_eq!(b.get_shelves(), vec![("To buy", 0), ("Novels", 1)]);
+        assert!(b
+            .books
+            .values()
+            .all(|book| !book.is_on_shelf("Work reading")));
+
+        // Books on a shelf which is not listed still count
+        b.get_mut_book(3)
+            .unwrap()
+            .shelves
+            .insert("Unlisted".to_string());
+        assert!(b.has_shelf("Unlisted"));
+    }
+
+    #[test]
+    fn aggregate() {
+        let mut other = test_bookcase();
+        other.name = "Other".to_string();
+        other.remove_book(2);
+        other.create_shelf("Fiction");
+        let mut b = Bookcase::aggregate(vec![
+            ("a.yaml".to_string(), test_bookcase()),
+            ("b.yaml".to_string(), other),
+        ]);
+
+        assert!(b.read_only);
+        assert_eq!(b.name, "Bookcase name + Other");
+        assert_eq!(b.shelves, vec!["Fiction"]);
+        assert_eq!(b.books.len(), 5);
+        assert_eq!(b.books[&3].source.as_deref(), Some("a.yaml"));
+        assert_eq!(b.books[&5].source.as_deref(), Some("b.yaml"));
+        assert_eq!(b.books[&5].title, test_book3().title);
+        assert!(b.close("unused.yaml", 0).is_err());
+    }
+
+    #[test]
+    fn merge_duplicate() {
+        let mut b = test_bookcase();
+        b.get_mut_book(1).unwrap().tags = HashSet::from(["alpha".to_string()]);
+        let duplicate = b.get_mut_book(3).unwrap();
+        duplicate.tags = HashSet::from(["gamma".to_string()]);
+        duplicate.authors = vec![
+            "Author, Authoritative".into(),
+            "Cuthoritative Author".into(),
+        ];
+        duplicate.pages = Some(200);
+        duplicate.finish();
+
+        b.merge_duplicate(1, 3).unwrap();
+        assert!(!b.books.contains_key(&3));
+        let kept = &b.books[&1];
+        assert_eq!(kept.title, "Titular Title");
+        assert_eq!(
+            kept.authors_string(),
+            "Authoritative Author; Cuthoritative Author"
+        );
+        assert_eq!(kept.read_state(), &Read::Read);
+        assert_eq!(kept.pages, Some(200));
+        assert_eq!(kept.history().len(), 1);
+        assert_eq!(
+            kept.tags,
+            HashSet::from(["alpha".to_string(), "gamma".to_string()])
+        );
+        assert!(b.merge_duplicate(1, 3).is_err());
+    }
+
+    #[test]
+    fn authors() {
+        let mut b = Bookcase::new();
+        b.add_book("One".to_string(), vec!["Dickens, Charles".into()]);
+        b.add_book("Two".to_string(), vec!["C. Dickens".into()]);
+        b.add_book("Three".to_string(), vec!["Ursula K. Le Guin".into()]);
+        b.add_book("Four".to_string(), vec!["Boz".into()]);
+        b.add_book("Six".to_string(), vec!["Kurt Vonnegut, Jr.".into()]);
+        // Names are kept as given without an author record
+        assert_eq!(b.books[&1].authors_string(), "Dickens, Charles");
+        assert_eq!(b.books[&5].authors_string(), "Kurt Vonnegut, Jr.");
+        assert_eq!(
+            b.get_authors(),
+            vec![
+                "Boz",
+                "C. Dickens",
+                "Charles Dickens",
+                "Ursula K. Le Guin",
+                "Kurt Vonnegut, Jr."
+            ]
+        );
+        assert_eq!(
+            b.similar_authors(),
+            vec![("C. Dickens".to_string(), "Charles Dickens".to_string())]
+        );
+
+        let changed = b.merge_authors(
+            "Charles Dickens",
+            &["C. Dickens".to_string(), "Boz".to_string()],
+        );
+        assert_eq!(changed, 3);
+        assert_eq!(
+            b.get_authors(),
+            vec!["Charles Dickens", "Ursula K. Le Guin", "Kurt Vonnegut, Jr."]
+        );
+        assert_eq!(b.books[&2].authors_string(), "Charles Dickens");
+
+        // New books use the canonical name
+        let id = b.add_book("Five".to_string(), vec!["boz".into()]);
+        assert_eq!(b.books[&id].authors_string(), "Charles Dickens");
+
+        b.set_author_sort_name("Ursula K. Le Guin", Some("Guin".to_string()));
+        assert_eq!(
+            b.cmp_books(&b.books[&3], &b.books[&1], &Sorting::Author),
+            std::cmp::Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn close_unchanged() {
+        let dir = std::env::temp_dir().join(format!("booktop-close-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bookcase.booktop.yaml");
+        let contents = serde_yaml::to_string(&test_bookcase()).unwrap();
+        fs::write(&path, &contents).unwrap();
+        for _ in 0..3 {
+            Bookcase::open(&path).unwrap().close(&path, 3).unwrap();
+        }
+        assert!(backup::list(&path).is_empty());
+        // Books without UUIDs are only given them when the file is written
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+        let mut bookcase = Bookcase::open(&path).unwrap();
+        bookcase.add_book("Emma".to_string(), vec!["Jane Austen".into()]);
+        bookcase.close(&path, 3).unwrap();
+        assert_eq!(backup::list(&path).len(), 1);
+        let bookcase = Bookcase::open(&path).unwrap();
+        assert!(bookcase.books.values().all(|b| b.uuid.is_some()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,410 @@
+use crate::isbn::Isbn;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    EmptyTitle,
+    Whitespace,
+    TagCase,
+    InvalidIsbn,
+    DateOrder,
+    ZeroId,
+    /// The file cannot be loaded as a bookcase at all
+    Unreadable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    /// Number of the book, or `None` for problems with the bookcase as a whole
+    pub book: Option<u64>,
+    pub check: Check,
+    pub message: String,
+    pub fixed: bool,
+}
+
+impl Problem {
+    pub fn unreadable(message: String) -> Problem {
+        Problem {
+            book: None,
+            check: Check::Unreadable,
+            message,
+            fixed: false,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(book) = self.book {
+            write!(f, "{}: ", book)?;
+        }
+        write!(f, "{}", self.message)?;
+        if self.fixed {
+            write!(f, " (fixed)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Output {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("{} is not a report format (text, json)", s.trim())),
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Output::Text => write!(f, "text"),
+            Output::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Problems found in a file, in a form for scripts
+#[derive(Debug, Serialize)]
+pub struct Report<'p> {
+    pub file: String,
+    pub problems: &'p [Problem],
+    pub fixed: usize,
+    pub remaining: usize,
+}
+
+impl<'p> Report<'p> {
+    pub fn new(file: String, problems: &'p [Problem]) -> Report<'p> {
+        let fixed = problems.iter().filter(|p| p.fixed).count();
+        Report {
+            file,
+            problems,
+            fixed,
+            remaining: problems.len() - fixed,
+        }
+    }
+}
+
+fn tags(book: &mut Value) -> Option<&mut Vec<Value>> {
+    match book.get_mut("tags") {
+        Some(Value::Sequence(s)) => Some(s),
+        _ => None,
+    }
+}
+
+struct Checker {
+    problems: Vec<Problem>,
+    fix: bool,
+}
+
+impl Checker {
+    fn report(&mut self, book: Option<u64>, check: Check, message: String, fixable: bool) {
+        self.problems.push(Problem {
+            book,
+            check,
+            message,
+            fixed: fixable && self.fix,
+        })
+    }
+    /// Report surrounding or repeated whitespace in a string, tidying it when fixing
+    fn whitespace(&mut self, id: u64, what: &str, value: &mut Value) {
+        let Some(s) = value.as_str() else {
+            return;
+        };
+        let tidy = s.split_whitespace().collect::<Vec<_>>().join(" ");
+        if tidy != s {
+            self.report(
+                Some(id),
+                Check::Whitespace,
+                format!("{} {:?} has extra whitespace", what, s),
+                true,
+            );
+            if self.fix {
+                *value = Value::String(tidy);
+            }
+        }
+    }
+    fn book(&mut self, id: u64, book: &mut Mapping) {
+        match book.get_mut("title") {
+            Some(Value::String(t)) if !t.trim().is_empty() => {
+                self.whitespace(id, "Title", book.get_mut("title").expect("title checked"))
+            }
+            _ => self.report(
+                Some(id),
+                Check::EmptyTitle,
+                "Title is empty".to_string(),
+                false,
+            ),
+        }
+        if let Some(Value::Sequence(authors)) = book.get_mut("authors") {
+            for author in authors.iter_mut() {
+                let name = match author {
+                    Value::Mapping(m) => m.get_mut("name"),
+                    a => Some(a),
+                };
+                if let Some(name) = name {
+                    self.whitespace(id, "Author", name)
+                }
+            }
+        }
+        if let Some(Value::Sequence(tags)) = book.get_mut("tags") {
+            for tag in tags.iter_mut() {
+                self.whitespace(id, "Tag", tag)
+            }
+        }
+        let isbn = match book.get("isbn") {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(Value::Number(n)) => Some(n.to_string()),
+            _ => None,
+        };
+        if let Some(Err(e)) = isbn.map(|i| i.parse::<Isbn>()) {
+            self.report(Some(id), Check::InvalidIsbn, e, false)
+        }
+        self.dates(id, book);
+    }
+    fn dates(&mut self, id: u64, book: &Mapping) {
+        let entries = |key: &str| -> Vec<Mapping> {
+            match book.get(key) {
+                Some(Value::Sequence(s)) => {
+                    s.iter().filter_map(|v| v.as_mapping().cloned()).collect()
+                }
+                _ => Vec::new(),
+            }
+        };
+        let time = |m: &Mapping, key: &str| -> Option<DateTime<Utc>> {
+            m.get(key)
+                .and_then(|v| serde_yaml::from_value(v.clone()).ok())
+        };
+        let date = |m: &Mapping, key: &str| -> Option<NaiveDate> {
+            m.get(key)
+                .and_then(|v| serde_yaml::from_value(v.clone()).ok())
+        };
+
+        let history: Vec<_> = entries("history")
+            .iter()
+            .filter_map(|c| time(c, "at"))
+            .collect();
+        if let Some(w) = history.windows(2).find(|w| w[1] < w[0]) {
+            self.report(
+                Some(id),
+                Check::DateOrder,
+                format!("History goes back from {} to {}", w[0], w[1]),
+                false,
+            )
+        }
+        for session in entries("sessions") {
+            if let (Some(start), Some(end)) = (time(&session, "start"), time(&session, "end")) {
+                if end < start {
+                    self.report(
+                        Some(id),
+                        Check::DateOrder,
+                        format!(
+                            "Reading session ends at {} before starting at {}",
+                            end, start
+                        ),
+                        false,
+                    )
+                }
+            }
+        }
+        for loan in entries("loans") {
+            let Some(lent) = date(&loan, "lent") else {
+                continue;
+            };
+            for key in ["due", "returned"] {
+                if let Some(d) = date(&loan, key).filter(|d| *d < lent) {
+                    self.report(
+                        Some(id),
+                        Check::DateOrder,
+                        format!("Loan is {} on {} before being lent on {}", key, d, lent),
+                        false,
+                    )
+                }
+            }
+        }
+    }
+    /// Tags across all books which differ only by case, using the most common spelling
+    fn tag_case(&mut self, books: &mut Mapping) {
+        let mut spellings: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
+        for book in books.values_mut() {
+            for tag in tags(book).into_iter().flatten().filter_map(|t| t.as_str()) {
+                *spellings
+                    .entry(tag.to_lowercase())
+                    .or_default()
+                    .entry(tag.to_string())
+                    .or_default() += 1;
+            }
+        }
+        for counts in spellings.values().filter(|c| c.len() > 1) {
+            let (preferred, _) = counts
+                .iter()
+                .max_by(|(s, n), (t, m)| n.cmp(m).then(t.cmp(s)))
+                .expect("several spellings");
+            let others: Vec<_> = counts.keys().filter(|s| *s != preferred).collect();
+            self.report(
+                None,
+                Check::TagCase,
+                format!(
+                    "Tag {:?} is also spelled {}",
+                    preferred,
+                    others
+                        .iter()
+                        .map(|s| format!("{:?}", s))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                true,
+            );
+            if !self.fix {
+                continue;
+            }
+            for book in books.values_mut() {
+                let Some(tags) = tags(book) else {
+                    continue;
+                };
+                for tag in tags.iter_mut() {
+                    if tag.as_str().is_some_and(|t| others.iter().any(|o| *o == t)) {
+                        *tag = Value::String(preferred.clone());
+                    }
+                }
+                let mut seen = Vec::new();
+                tags.retain(|t| match seen.contains(t) {
+                    true => false,
+                    false => {
+                        seen.push(t.clone());
+                        true
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Check a bookcase, as YAML at the current schema version, for problems
+///
+/// With `fix`, safe corrections are made to the bookcase and the problems marked as fixed.
+pub fn check(bookcase: &mut Value, fix: bool) -> Vec<Problem> {
+    let mut checker = Checker {
+        problems: Vec::new(),
+        fix,
+    };
+    let Some(Value::Mapping(books)) = bookcase.get_mut("books") else {
+        return checker.problems;
+    };
+    for (id, book) in books.iter_mut() {
+        if let (Some(id), Value::Mapping(book)) = (id.as_u64(), book) {
+            checker.book(id, book);
+        }
+    }
+    checker.tag_case(books);
+
+    let zero = Value::from(0u64);
+    if books.contains_key(&zero) {
+        let new_id = books.keys().filter_map(Value::as_u64).max().unwrap_or(0) + 1;
+        checker.report(
+            Some(0),
+            Check::ZeroId,
+            format!("Book number 0 is not allowed, renumbering as {}", new_id),
+            true,
+        );
+        if fix {
+            let book = books.remove(&zero).expect("book 0 present");
+            books.insert(Value::from(new_id), book);
+        }
+    }
+    checker.problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_and_fix() {
+        let yaml = "
+version: 1
+books:
+  0:
+    title: ' Emma '
+    authors: ['Jane  Austen', {name: 'Someone ', role: Editor}]
+    tags: [classic, Fiction]
+    isbn: 9780141439563
+  2:
+    title: ''
+    authors: []
+    tags: [fiction, Classic, Fiction]
+    isbn: 978-0-14-143956-4
+    history:
+      - {read: Reading, at: 2024-02-01T00:00:00Z}
+      - {read: Read, at: 2024-01-01T00:00:00Z}
+    loans:
+      - {borrower: Sam, lent: 2024-03-01, returned: 2024-02-01}
+";
+        let mut value: Value = serde_yaml::from_str(yaml).unwrap();
+        let original = value.clone();
+        let checks = |problems: &[Problem]| -> Vec<(Option<u64>, Check, bool)> {
+            problems
+                .iter()
+                .map(|p| (p.book, p.check, p.fixed))
+                .collect()
+        };
+
+        let problems = check(&mut value, false);
+        assert_eq!(value, original);
+        assert_eq!(
+            checks(&problems),
+            vec![
+                (Some(0), Check::Whitespace, false),
+                (Some(0), Check::Whitespace, false),
+                (Some(0), Check::Whitespace, false),
+                (Some(2), Check::EmptyTitle, false),
+                (Some(2), Check::InvalidIsbn, false),
+                (Some(2), Check::DateOrder, false),
+                (Some(2), Check::DateOrder, false),
+                (None, Check::TagCase, false),
+                (None, Check::TagCase, false),
+                (Some(0), Check::ZeroId, false),
+            ]
+        );
+
+        let problems = check(&mut value, true);
+        assert_eq!(problems.iter().filter(|p| p.fixed).count(), 6);
+        let expected: Value = serde_yaml::from_str(
+            "{title: Emma, authors: [Jane Austen, {name: Someone, role: Editor}], \
+             tags: [Classic, Fiction], isbn: 9780141439563}",
+        )
+        .unwrap();
+        assert_eq!(value["books"][3], expected);
+        assert_eq!(
+            value["books"][2]["tags"],
+            serde_yaml::from_str::<Value>("[Fiction, Classic]").unwrap()
+        );
+
+        let remaining = check(&mut value, false);
+        assert_eq!(
+            checks(&remaining),
+            vec![
+                (Some(2), Check::EmptyTitle, false),
+                (Some(2), Check::InvalidIsbn, false),
+                (Some(2), Check::DateOrder, false),
+                (Some(2), Check::DateOrder, false),
+            ]
+        );
+    }
+}
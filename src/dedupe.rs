@@ -0,0 +1,242 @@
+use crate::author;
+use crate::book::Book;
+use crate::books::Bookcase;
+use std::fmt;
+
+/// Similarity of title and authors above which books are taken to be duplicates
+pub const DEFAULT_SIMILARITY: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Isbn,
+    TitleAndAuthor,
+    Similar(f64),
+}
+
+impl Reason {
+    /// Whether the books are certainly the same, so can be merged without asking
+    pub fn is_certain(&self) -> bool {
+        !matches!(self, Reason::Similar(_))
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Isbn => write!(f, "same ISBN"),
+            Reason::TitleAndAuthor => write!(f, "same title and author"),
+            Reason::Similar(s) => write!(f, "similar title and author, {:.0}%", s * 100.0),
+        }
+    }
+}
+
+/// A likely duplicate, to be merged into the book kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub keep: usize,
+    pub duplicate: usize,
+    pub reason: Reason,
+}
+
+/// Lowercase words without punctuation or a leading article
+fn normalise(s: &str) -> String {
+    let lower = s.to_lowercase();
+    let mut words = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .peekable();
+    if words.next_if(|w| ["the", "a", "an"].contains(w)).is_some() && words.peek().is_none() {
+        return lower.trim().to_string();
+    }
+    words.collect::<Vec<_>>().join(" ")
+}
+
+fn digits(s: &str) -> String {
+    s.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// Share of words matching a word in the other title, allowing for misspellings
+///
+/// Words are compared rather than whole titles, as titles in a series often share all but
+/// a word or two, such as "Harry Potter and the Chamber of Secrets" and "... Goblet of Fire".
+fn title_similarity(a: &str, b: &str) -> f64 {
+    let (a, mut b): (Vec<_>, Vec<_>) = (a.split(' ').collect(), b.split(' ').collect());
+    let words = a.len().max(b.len());
+    if words == 0 {
+        return 1.0;
+    }
+    let mut matched = 0;
+    for word in a {
+        if let Some(i) = b
+            .iter()
+            .position(|w| strsim::jaro_winkler(word, w) >= WORD_SIMILARITY)
+        {
+            b.swap_remove(i);
+            matched += 1;
+        }
+    }
+    matched as f64 / words as f64
+}
+
+/// Similarity above which two words are taken to be the same word misspelled
+const WORD_SIMILARITY: f64 = 0.9;
+
+/// Whether two books look like the same one, and why
+pub fn compare(a: &Book, b: &Book, similarity: f64) -> Option<Reason> {
+    match (&a.isbn, &b.isbn) {
+        (Some(i), Some(j)) if i == j => return Some(Reason::Isbn),
+        // Most likely different editions
+        (Some(_), Some(_)) => return None,
+        _ => {}
+    }
+    if let (Some(s), Some(t)) = (&a.series, &b.series) {
+        if s.volume != t.volume {
+            return None;
+        }
+    }
+    let (ta, tb) = (normalise(&a.title), normalise(&b.title));
+    // Numbered volumes have near identical titles
+    if digits(&ta) != digits(&tb) {
+        return None;
+    }
+    // Names may be given surname first
+    let authors = |b: &Book| {
+        b.author_names()
+            .map(|n| normalise(&author::display_name(n)))
+            .collect::<Vec<_>>()
+    };
+    let (aa, ab) = (authors(a), authors(b));
+    if ta == tb && (aa.iter().any(|n| ab.contains(n)) || aa.is_empty() && ab.is_empty()) {
+        return Some(Reason::TitleAndAuthor);
+    }
+    let author_similarity = match (aa.is_empty(), ab.is_empty()) {
+        (true, true) => 1.0,
+        _ => aa
+            .iter()
+            .flat_map(|n| ab.iter().map(|m| strsim::jaro_winkler(n, m)))
+            .fold(0.0, f64::max),
+    };
+    let score = title_similarity(&ta, &tb).min(author_similarity);
+    (score >= similarity).then_some(Reason::Similar(score))
+}
+
+/// Likely duplicates among all books, keeping the lower numbered of each pair
+pub fn find_duplicates(books: &Bookcase, similarity: f64) -> Vec<Candidate> {
+    let books: Vec<_> = books.get_books().into_iter().collect();
+    let mut candidates = Vec::new();
+    for (i, (keep, a)) in books.iter().enumerate() {
+        for (duplicate, b) in &books[i + 1..] {
+            if let Some(reason) = compare(a, b, similarity) {
+                candidates.push(Candidate {
+                    keep: **keep,
+                    duplicate: **duplicate,
+                    reason,
+                })
+            }
+        }
+    }
+    candidates
+}
+
+/// Other books which look like the same one as a book
+pub fn duplicates_of(books: &Bookcase, id: usize, similarity: f64) -> Vec<(usize, Reason)> {
+    let Some(book) = books.get_book(&id) else {
+        return Vec::new();
+    };
+    books
+        .get_books()
+        .into_iter()
+        .filter(|(k, _)| **k != id)
+        .filter_map(|(k, b)| compare(book, b, similarity).map(|r| (*k, r)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, author: &str) -> Book {
+        Book::new(title.to_string(), vec![author.into()])
+    }
+
+    #[test]
+    fn compare_books() {
+        let a = book("The Great Gatsby", "F. Scott Fitzgerald");
+        let s = DEFAULT_SIMILARITY;
+        assert_eq!(
+            compare(&a, &book("Great Gatsby", "F Scott Fitzgerald"), s),
+            Some(Reason::TitleAndAuthor)
+        );
+        assert!(matches!(
+            compare(&a, &book("The Great Gatsbey", "F. Scott Fitzgerald"), s),
+            Some(Reason::Similar(_))
+        ));
+        assert_eq!(
+            compare(&a, &book("The Great Gatsby", "Someone Else"), s),
+            None
+        );
+        assert_eq!(
+            compare(&a, &book("Tender Is the Night", "F. Scott Fitzgerald"), s),
+            None
+        );
+        assert_eq!(
+            compare(
+                &book("Dune 2", "Frank Herbert"),
+                &book("Dune 3", "Frank Herbert"),
+                s
+            ),
+            None
+        );
+
+        // Titles sharing most of their words are not enough
+        let rowling = |title: &str| book(title, "J.K. Rowling");
+        assert_eq!(
+            compare(
+                &rowling("Harry Potter and the Chamber of Secrets"),
+                &rowling("Harry Potter and the Goblet of Fire"),
+                s
+            ),
+            None
+        );
+        assert_eq!(
+            compare(
+                &book("The Lord of the Rings", "J.R.R. Tolkien"),
+                &book("The Lord of the Flies", "J.R.R. Tolkien"),
+                s
+            ),
+            None
+        );
+        assert!(!Reason::Similar(1.0).is_certain());
+
+        let mut i = book("Gatsby", "Anonymous");
+        i.isbn = "9780141439563".parse().ok();
+        let mut j = a.clone();
+        j.isbn = i.isbn.clone();
+        assert_eq!(compare(&i, &j, s), Some(Reason::Isbn));
+        j.isbn = "0-8044-2957-X".parse().ok();
+        assert_eq!(compare(&a, &j, s), Some(Reason::TitleAndAuthor));
+        let mut k = a.clone();
+        k.isbn = i.isbn.clone();
+        assert_eq!(compare(&k, &j, s), None);
+    }
+
+    #[test]
+    fn find() {
+        let mut books = Bookcase::new();
+        books.add_book("Emma".to_string(), vec!["Jane Austen".into()]);
+        books.add_book("Persuasion".to_string(), vec!["Jane Austen".into()]);
+        books.add_book("EMMA.".to_string(), vec!["Jane Austen".into()]);
+        assert_eq!(
+            find_duplicates(&books, DEFAULT_SIMILARITY),
+            vec![Candidate {
+                keep: 1,
+                duplicate: 3,
+                reason: Reason::TitleAndAuthor
+            }]
+        );
+        assert_eq!(
+            duplicates_of(&books, 3, DEFAULT_SIMILARITY),
+            vec![(1, Reason::TitleAndAuthor)]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    Parse {
+        path: Option<PathBuf>,
+        message: String,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    Validation(String),
+    /// The bookcase or journal could not be written out as YAML
+    Serialise {
+        path: PathBuf,
+        message: String,
+    },
+    /// The file was changed by something else after it was opened
+    Conflict(PathBuf),
+}
+
+impl Error {
+    pub fn io<P: AsRef<Path>>(path: P, source: io::Error) -> Error {
+        Error::Io {
+            path: Some(path.as_ref().to_path_buf()),
+            source,
+        }
+    }
+    pub fn parse<P: AsRef<Path>>(path: P, source: serde_yaml::Error) -> Error {
+        match Error::from(source) {
+            Error::Parse {
+                message,
+                line,
+                column,
+                ..
+            } => Error::Parse {
+                path: Some(path.as_ref().to_path_buf()),
+                message,
+                line,
+                column,
+            },
+            e => e,
+        }
+    }
+    pub fn serialise<P: AsRef<Path>>(path: P, source: serde_yaml::Error) -> Error {
+        Error::Serialise {
+            path: path.as_ref().to_path_buf(),
+            message: source.to_string(),
+        }
+    }
+    /// Exit codes follow sysexits.h
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Io { .. } => ExitCode::from(74),
+            Error::Parse { .. } => ExitCode::from(65),
+            Error::Validation(_) => ExitCode::from(1),
+            Error::Serialise { .. } => ExitCode::from(70),
+            Error::Conflict(_) => ExitCode::from(75),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "Could not access {}: {}", path.display(), source),
+            Error::Io { path: None, source } => write!(f, "{}", source),
+            Error::Parse {
+                path,
+                message,
+                line,
+                column,
+            } => {
+                write!(f, "Could not read bookcase")?;
+                if let Some(path) = path {
+                    write!(f, " {}", path.display())?;
+                }
+                if let (Some(line), Some(column)) = (line, column) {
+                    write!(f, " at line {}, column {}", line, column)?;
+                }
+                write!(f, ": {}", message)
+            }
+            Error::Validation(message) => write!(f, "{}", message),
+            Error::Serialise { path, message } => {
+                write!(f, "Could not serialise {}: {}", path.display(), message)
+            }
+            Error::Conflict(path) => write!(
+                f,
+                "{} was changed since it was opened, not saving",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(source: serde_yaml::Error) -> Self {
+        let location = source.location();
+        Error::Parse {
+            path: None,
+            // Location is reported separately, so drop it from serde_yaml's message
+            message: source
+                .to_string()
+                .split(" at line ")
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::books::Bookcase;
+
+    #[test]
+    fn parse_location() {
+        let e: Error =
+            serde_yaml::from_str::<Bookcase>("name: Bookcase\nbooks:\n  1:\n    title: [\n")
+                .unwrap_err()
+                .into();
+        match &e {
+            Error::Parse { line, column, .. } => {
+                assert_eq!(line, &Some(4));
+                assert!(column.is_some());
+            }
+            _ => panic!("Expected a parse error, got {:?}", e),
+        }
+        assert!(e
+            .to_string()
+            .starts_with("Could not read bookcase at line 4, column"));
+    }
+}
@@ -0,0 +1,289 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Value of a user-defined field on a book
+#[derive(Debug, Clone)]
+pub enum FieldValue {
+    Bool(bool),
+    Number(f64),
+    Date(NaiveDate),
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FieldType {
+    Bool,
+    Number,
+    Date,
+    String,
+}
+
+impl FromStr for FieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "bool" => Ok(FieldType::Bool),
+            "number" => Ok(FieldType::Number),
+            "date" => Ok(FieldType::Date),
+            "string" => Ok(FieldType::String),
+            _ => Err(format!(
+                "{} is not a field type (bool, number, date, string)",
+                s
+            )),
+        }
+    }
+}
+
+impl FieldValue {
+    pub fn parse_as(s: &str, field_type: FieldType) -> Result<FieldValue, String> {
+        let s = s.trim();
+        match field_type {
+            FieldType::Bool => s
+                .parse()
+                .map(FieldValue::Bool)
+                .map_err(|_| format!("{} is not true or false", s)),
+            FieldType::Number => match s.parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(FieldValue::Number(n)),
+                _ => Err(format!("{} is not a number", s)),
+            },
+            FieldType::Date => NaiveDate::parse_from_str(s, DATE_FORMAT)
+                .map(FieldValue::Date)
+                .map_err(|_| format!("{} is not a date (YYYY-MM-DD)", s)),
+            FieldType::String => Ok(FieldValue::String(s.to_string())),
+        }
+    }
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldValue::Bool(_) => FieldType::Bool,
+            FieldValue::Number(_) => FieldType::Number,
+            FieldValue::Date(_) => FieldType::Date,
+            FieldValue::String(_) => FieldType::String,
+        }
+    }
+    /// Whether `self` matches `other`, ignoring case for strings
+    pub fn matches(&self, other: &FieldValue) -> bool {
+        match (self, other) {
+            (FieldValue::String(s), FieldValue::String(o)) => s.eq_ignore_ascii_case(o),
+            _ => self == other,
+        }
+    }
+}
+
+/// Infers the type, trying bool, number and date before falling back to a string
+impl FromStr for FieldValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [FieldType::Bool, FieldType::Number, FieldType::Date]
+            .into_iter()
+            .find_map(|t| FieldValue::parse_as(s, t).ok())
+            .map_or_else(|| FieldValue::parse_as(s, FieldType::String), Ok)
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldValue::Bool(b) => write!(f, "{}", b),
+            FieldValue::Number(n) => write!(f, "{}", n),
+            FieldValue::Date(d) => write!(f, "{}", d.format(DATE_FORMAT)),
+            FieldValue::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl PartialEq for FieldValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FieldValue {}
+
+impl PartialOrd for FieldValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders values of the same type naturally, and otherwise by type
+impl Ord for FieldValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (FieldValue::Bool(s), FieldValue::Bool(o)) => s.cmp(o),
+            (FieldValue::Number(s), FieldValue::Number(o)) => s.total_cmp(o),
+            (FieldValue::Date(s), FieldValue::Date(o)) => s.cmp(o),
+            (FieldValue::String(s), FieldValue::String(o)) => s.cmp(o),
+            _ => (self.field_type() as u8).cmp(&(other.field_type() as u8)),
+        }
+    }
+}
+
+/// Strings which would be read back as another type, such as "2024-01-31", are tagged `!string`
+impl Serialize for FieldValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FieldValue::Bool(b) => serializer.serialize_bool(*b),
+            FieldValue::Number(n) => serializer.serialize_f64(*n),
+            FieldValue::Date(_) => serializer.serialize_str(&self.to_string()),
+            FieldValue::String(s) => match s.parse::<FieldValue>() {
+                Ok(FieldValue::String(_)) => serializer.serialize_str(s),
+                _ => serializer.serialize_newtype_variant("FieldValue", 3, STRING_TAG, s),
+            },
+        }
+    }
+}
+
+const STRING_TAG: &str = "string";
+
+struct FieldValueVisitor;
+
+impl<'de> serde::de::Visitor<'de> for FieldValueVisitor {
+    type Value = FieldValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a bool, number, date or string")
+    }
+    fn visit_bool<E: serde::de::Error>(self, b: bool) -> Result<FieldValue, E> {
+        Ok(FieldValue::Bool(b))
+    }
+    fn visit_i64<E: serde::de::Error>(self, n: i64) -> Result<FieldValue, E> {
+        Ok(FieldValue::Number(n as f64))
+    }
+    fn visit_u64<E: serde::de::Error>(self, n: u64) -> Result<FieldValue, E> {
+        Ok(FieldValue::Number(n as f64))
+    }
+    fn visit_f64<E: serde::de::Error>(self, n: f64) -> Result<FieldValue, E> {
+        Ok(FieldValue::Number(n))
+    }
+    fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<FieldValue, E> {
+        Ok(match NaiveDate::parse_from_str(s, DATE_FORMAT) {
+            Ok(d) => FieldValue::Date(d),
+            Err(_) => FieldValue::String(s.to_string()),
+        })
+    }
+    fn visit_enum<A: serde::de::EnumAccess<'de>>(self, data: A) -> Result<FieldValue, A::Error> {
+        use serde::de::VariantAccess;
+        let (tag, value) = data.variant::<String>()?;
+        match tag.as_str() {
+            STRING_TAG => value.newtype_variant().map(FieldValue::String),
+            _ => Err(serde::de::Error::unknown_variant(&tag, &[STRING_TAG])),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FieldValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FieldValueVisitor)
+    }
+}
+
+/// Requirement on a field, either that it is present or that it has a value
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FieldFilter {
+    pub key: String,
+    pub value: Option<FieldValue>,
+}
+
+impl FieldFilter {
+    pub fn matches(&self, value: Option<&FieldValue>) -> bool {
+        match (&self.value, value) {
+            (None, Some(_)) => true,
+            (Some(wanted), Some(value)) => value.matches(wanted),
+            (_, None) => false,
+        }
+    }
+}
+
+/// Parses `key` or `key=value`
+impl FromStr for FieldFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key, Some(value.parse()?)),
+            None => (s, None),
+        };
+        match key.trim() {
+            "" => Err("Field name is empty".to_string()),
+            key => Ok(FieldFilter {
+                key: key.to_string(),
+                value,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infer() {
+        assert_eq!("true".parse(), Ok(FieldValue::Bool(true)));
+        assert_eq!("12.50".parse(), Ok(FieldValue::Number(12.5)));
+        assert_eq!(
+            "2024-02-29".parse(),
+            Ok(FieldValue::Date(
+                NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+            ))
+        );
+        assert_eq!(
+            "Shelf 3".parse(),
+            Ok(FieldValue::String("Shelf 3".to_string()))
+        );
+        assert_eq!(
+            FieldValue::parse_as("12", FieldType::String),
+            Ok(FieldValue::String("12".to_string()))
+        );
+        assert!(FieldValue::parse_as("twelve", FieldType::Number).is_err());
+    }
+
+    #[test]
+    fn serde() {
+        let values = vec![
+            FieldValue::Bool(false),
+            FieldValue::Number(3.0),
+            FieldValue::Date(NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()),
+            FieldValue::String("Alice".to_string()),
+        ];
+        let s = serde_yaml::to_string(&values).unwrap();
+        assert_eq!(s, "- false\n- 3.0\n- 2024-01-31\n- Alice\n");
+        let r: Vec<FieldValue> = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, values);
+
+        // Strings which look like other types keep their type
+        let values = vec![
+            FieldValue::String("2024-01-31".to_string()),
+            FieldValue::String("12".to_string()),
+            FieldValue::String("true".to_string()),
+        ];
+        let s = serde_yaml::to_string(&values).unwrap();
+        assert_eq!(
+            s,
+            "- !string 2024-01-31\n- !string '12'\n- !string 'true'\n"
+        );
+        let r: Vec<FieldValue> = serde_yaml::from_str(&s).unwrap();
+        assert_eq!(r, values);
+        let r: Vec<FieldValue> = serde_yaml::from_str("['12', 'true']").unwrap();
+        assert_eq!(r, values[1..]);
+    }
+
+    #[test]
+    fn filter() {
+        let f: FieldFilter = "recommended by=alice".parse().unwrap();
+        assert!(f.matches(Some(&FieldValue::String("Alice".to_string()))));
+        assert!(!f.matches(Some(&FieldValue::String("Bob".to_string()))));
+        assert!(!f.matches(None));
+
+        let f: FieldFilter = "price".parse().unwrap();
+        assert!(f.matches(Some(&FieldValue::Number(1.0))));
+        assert!(!f.matches(None));
+    }
+}
@@ -1,32 +1,46 @@
-use crate::book::{Book, Read};
+use crate::author;
+use crate::book::{Book, Format, Ownership, Read};
+use crate::field::FieldFilter;
 use std::collections::HashSet;
 use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Filter {
     pub author_match: Vec<Rc<str>>,
     pub read: HashSet<Read>,
     pub tags: Vec<String>,
+    pub fields: Vec<FieldFilter>,
+    pub formats: HashSet<Format>,
+    pub ownership: HashSet<Ownership>,
+    pub shelf: Option<String>,
 }
 
 impl Filter {
-    fn match_book(&self, book: &Book) -> bool {
+    pub fn match_book(&self, book: &Book) -> bool {
         (self.author_match.is_empty()
             || self
                 .author_match
                 .iter()
-                .any(|a| string_match(a, &book.author)))
+                .any(|a| book.contributor_names().any(|n| string_match(a, n))))
             && (self.read.is_empty() || self.read.contains(book.read_state()))
-            && (self.tags.is_empty() || self.tags.iter().any(|t| book.tags.contains(t)))
+            && (self.tags.is_empty() || self.tags.iter().any(|t| book.contains_tag(t)))
+            && self
+                .fields
+                .iter()
+                .all(|f| f.matches(book.fields.get(&f.key)))
+            && (self.formats.is_empty() || book.formats.iter().any(|f| self.formats.contains(f)))
+            && (self.ownership.is_empty()
+                || book.ownership.is_some_and(|o| self.ownership.contains(&o)))
+            && self.shelf.as_ref().is_none_or(|s| book.is_on_shelf(s))
     }
     pub fn filter_books<'b, T>(
         &'b self,
         books: Vec<(&'b T, &'b Book)>,
-    ) -> impl Iterator<Item = (&T, &Book)> {
+    ) -> impl Iterator<Item = (&'b T, &'b Book)> {
         books.into_iter().filter(|&(_, b)| self.match_book(b))
     }
 }
 
 fn string_match(s1: &str, s2: &str) -> bool {
-    s1.eq_ignore_ascii_case(s2)
+    author::same_name(s1, s2)
 }
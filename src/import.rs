@@ -0,0 +1,376 @@
+use crate::book::{Book, Format, Read};
+use crate::books::Bookcase;
+use crate::dedupe;
+use crate::error::Error;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+mod goodreads;
+mod librarything;
+mod storygraph;
+
+pub use goodreads::goodreads;
+pub use librarything::librarything;
+pub use storygraph::storygraph;
+
+/// Books read from another program's export, with the rows which could not be read
+#[derive(Debug, Default)]
+pub struct Export {
+    pub books: Vec<Book>,
+    pub skipped: Vec<String>,
+    pub mapping: Mapping,
+}
+
+/// Which columns of an export give which details of books
+#[derive(Debug, Default, PartialEq)]
+pub struct Mapping {
+    pub columns: Vec<(String, &'static str)>,
+    pub ignored: Vec<String>,
+}
+
+impl Mapping {
+    /// Map the columns found in an export using the columns known for its format
+    pub fn new<'c>(
+        found: impl IntoIterator<Item = &'c str>,
+        known: &[(&str, &'static str)],
+    ) -> Mapping {
+        let mut mapping = Mapping::default();
+        for column in found {
+            match known.iter().find(|(c, _)| *c == column) {
+                Some((_, field)) => mapping.columns.push((column.to_string(), field)),
+                None => mapping.ignored.push(column.to_string()),
+            }
+        }
+        mapping
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Columns:")?;
+        for (column, field) in &self.columns {
+            writeln!(f, "    {} -> {}", column, field)?;
+        }
+        match self.ignored.is_empty() {
+            true => write!(f, "No columns ignored"),
+            false => write!(f, "Ignored: {}", self.ignored.join(", ")),
+        }
+    }
+}
+
+/// What importing did with each book
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub added: Vec<usize>,
+    /// Existing books which took details from the import
+    pub merged: Vec<usize>,
+    /// Books added which look like books already in the bookcase, as (added, existing, reason)
+    pub possible: Vec<(usize, usize, dedupe::Reason)>,
+    /// Books already in the bookcase, and rows which could not be read
+    pub skipped: Vec<String>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Added {}, merged {} and skipped {} book{}",
+            self.added.len(),
+            self.merged.len(),
+            self.skipped.len(),
+            if self.skipped.len() == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Add books to a bookcase, merging any with the same ISBN, or title and author, as a book
+/// already in it
+///
+/// Books which are only similar to existing ones are added and reported as possible duplicates.
+pub fn import(bookcase: &mut Bookcase, export: Export, shelf: Option<&str>) -> Summary {
+    let mut summary = Summary {
+        skipped: export.skipped,
+        ..Summary::default()
+    };
+    for mut book in export.books {
+        bookcase.apply_author_records(&mut book);
+        if let Some(shelf) = shelf {
+            book.shelves.insert(shelf.to_string());
+            bookcase.create_shelf(shelf);
+        }
+        let matches: Vec<(usize, dedupe::Reason)> = bookcase
+            .get_books()
+            .into_iter()
+            .filter_map(|(id, b)| {
+                dedupe::compare(b, &book, dedupe::DEFAULT_SIMILARITY).map(|r| (*id, r))
+            })
+            .collect();
+        let existing = matches
+            .iter()
+            .find(|(_, reason)| reason.is_certain())
+            .map(|(id, _)| *id);
+        match existing.and_then(|id| Some((id, bookcase.get_mut_book(id)?))) {
+            Some((id, existing)) => {
+                let before = existing.clone();
+                let title = book.title.clone();
+                existing.absorb(book);
+                match *existing == before {
+                    true => summary
+                        .skipped
+                        .push(format!("{:?} is already in the bookcase as {}", title, id)),
+                    false => summary.merged.push(id),
+                }
+            }
+            None => {
+                let id = bookcase.insert_book(book);
+                summary.added.push(id);
+                summary.possible.extend(
+                    matches
+                        .into_iter()
+                        .map(|(existing, reason)| (id, existing, reason)),
+                );
+            }
+        }
+    }
+    summary
+}
+
+/// A row of a CSV or TSV export, with cells looked up by column name
+pub struct Row<'h> {
+    headers: &'h HashMap<String, usize>,
+    record: csv::StringRecord,
+}
+
+impl Row<'_> {
+    /// Text of a cell, if the column exists and the cell is not empty
+    pub fn get(&self, column: &str) -> Option<&str> {
+        self.headers
+            .get(column)
+            .and_then(|&i| self.record.get(i))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    }
+    pub fn parse<T: std::str::FromStr>(&self, column: &str) -> Option<T> {
+        self.get(column).and_then(|s| s.parse().ok())
+    }
+}
+
+/// Text of an export, which some programs write as UTF-16
+pub fn read_text(path: &Path) -> Result<String, Error> {
+    let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+    let text = match bytes.strip_prefix(&[0xff, 0xfe]) {
+        Some(utf16) => String::from_utf16_lossy(
+            &utf16
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        None => String::from_utf8(bytes).map_err(|e| {
+            Error::Validation(format!("{} is not UTF-8 text: {}", path.display(), e))
+        })?,
+    };
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+/// Read the rows of a delimited file with a header row, see [`parse_rows`]
+pub fn read_rows(
+    path: &Path,
+    delimiter: u8,
+    columns: &[(&str, &'static str)],
+    read: impl FnMut(&Row) -> Result<Book, String>,
+) -> Result<Export, Error> {
+    parse_rows(&read_text(path)?, delimiter, columns, read)
+        .map_err(|e| Error::Validation(format!("{}: {}", path.display(), e)))
+}
+
+/// Parse delimited text with a header row, giving each row to `read`
+///
+/// Rows for which `read` gives an error are skipped.
+pub fn parse_rows(
+    text: &str,
+    delimiter: u8,
+    columns: &[(&str, &'static str)],
+    mut read: impl FnMut(&Row) -> Result<Book, String>,
+) -> Result<Export, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        // Tab separated exports do not quote cells
+        .quoting(delimiter != b'\t')
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let csv_error = |e: csv::Error| e.to_string();
+    let names: Vec<String> = reader
+        .headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+    let headers: HashMap<String, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(i, h)| (h.clone(), i))
+        .collect();
+
+    let mut export = Export {
+        mapping: Mapping::new(names.iter().map(String::as_str), columns),
+        ..Export::default()
+    };
+    for record in reader.records() {
+        let row = Row {
+            headers: &headers,
+            record: record.map_err(csv_error)?,
+        };
+        match read(&row) {
+            Ok(book) => export.books.push(book),
+            Err(e) => {
+                let line = row.record.position().map_or(0, |p| p.line());
+                export.skipped.push(format!("line {}: {}", line, e))
+            }
+        }
+    }
+    Ok(export)
+}
+
+/// Split a cell holding a list, dropping empty items
+pub fn split<'s>(s: Option<&'s str>, separators: &'s [char]) -> impl Iterator<Item = &'s str> {
+    s.into_iter()
+        .flat_map(move |s| s.split(separators))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+/// Read state from the name of a shelf, status or collection, such as "currently-reading"
+pub fn read_state(shelf: &str) -> Option<Read> {
+    let shelf = shelf.to_lowercase().replace([' ', '_'], "-");
+    match shelf.as_str() {
+        "read" | "read-but-unowned" => Some(Read::Read),
+        "currently-reading" => Some(Read::Reading),
+        "to-read" => Some(Read::Unread),
+        s if ["dnf", "did-not-finish", "abandon", "paused"]
+            .iter()
+            .any(|d| s.contains(d)) =>
+        {
+            Some(Read::Stopped)
+        }
+        _ => None,
+    }
+}
+
+/// Format from a description of the binding or media, such as "Kindle Edition"
+pub fn format(binding: &str) -> Option<Format> {
+    let binding = binding.to_lowercase();
+    if ["kindle", "ebook", "nook", "digital"]
+        .iter()
+        .any(|b| binding.contains(b))
+    {
+        Some(Format::Ebook)
+    } else if binding.contains("audio") {
+        Some(Format::Audiobook)
+    } else if [
+        "hardcover",
+        "paper",
+        "mass market",
+        "board",
+        "library binding",
+    ]
+    .iter()
+    .any(|b| binding.contains(b))
+    {
+        Some(Format::Physical)
+    } else {
+        None
+    }
+}
+
+/// A date as the start of the day, for exports which only give the date of changes
+pub fn parse_date(s: &str, format: &str) -> Option<DateTime<Utc>> {
+    NaiveDate::parse_from_str(s.trim(), format)
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_books() {
+        let mut bookcase = Bookcase::new();
+        bookcase.add_book("Emma".to_string(), vec!["Jane Austen".into()]);
+
+        let book = |title: &str, author: &str| Book::new(title.to_string(), vec![author.into()]);
+        let mut emma = book("Emma", "Austen, Jane");
+        emma.pages = Some(474);
+        let export = || Export {
+            books: vec![emma.clone(), book("Persuasion", "Jane Austen")],
+            skipped: vec!["line 4: no title".to_string()],
+            mapping: Mapping::default(),
+        };
+
+        let summary = import(&mut bookcase, export(), Some("Austen"));
+        assert_eq!(summary.added, vec![2]);
+        assert_eq!(summary.merged, vec![1]);
+        assert_eq!(summary.skipped.len(), 1);
+        assert_eq!(bookcase.get_book(&1).unwrap().pages, Some(474));
+        assert_eq!(
+            bookcase.get_book(&1).unwrap().authors_string(),
+            "Jane Austen"
+        );
+        assert!(bookcase.get_book(&2).unwrap().is_on_shelf("Austen"));
+
+        // Importing again changes nothing
+        let summary = import(&mut bookcase, export(), Some("Austen"));
+        assert!(summary.added.is_empty() && summary.merged.is_empty());
+        assert_eq!(summary.skipped.len(), 3);
+        assert_eq!(summary.to_string(), "Added 0, merged 0 and skipped 3 books");
+    }
+
+    #[test]
+    fn import_similar() {
+        let mut bookcase = Bookcase::new();
+        let rowling = vec!["J.K. Rowling".into()];
+        bookcase.add_book(
+            "Harry Potter and the Chamber of Secrets".to_string(),
+            rowling.clone(),
+        );
+        bookcase.add_book("Emma".to_string(), vec!["Jane Austen".into()]);
+
+        let mut goblet = Book::new("Harry Potter and the Goblet of Fire".to_string(), rowling);
+        goblet.series = Some(crate::book::Series {
+            name: "Harry Potter".to_string(),
+            volume: "4".parse().ok(),
+        });
+        let emma = Book::new("Emmma".to_string(), vec!["Jane Austen".into()]);
+        let export = Export {
+            books: vec![goblet, emma],
+            ..Export::default()
+        };
+
+        // Similar books are added rather than merged
+        let summary = import(&mut bookcase, export, None);
+        assert_eq!(summary.added, vec![3, 4]);
+        assert!(summary.merged.is_empty());
+        assert!(matches!(
+            summary.possible[..],
+            [(4, 2, dedupe::Reason::Similar(_))]
+        ));
+        assert_eq!(bookcase.get_book(&1).unwrap().series, None);
+    }
+
+    #[test]
+    fn mapping() {
+        let mapping = Mapping::new(["Title", "Moods"], &[("Title", "title"), ("Tags", "tags")]);
+        assert_eq!(
+            mapping.to_string(),
+            "Columns:\n    Title -> title\nIgnored: Moods"
+        );
+        assert_eq!(read_state("Currently reading"), Some(Read::Reading));
+        assert_eq!(read_state("did-not-finish"), Some(Read::Stopped));
+        assert_eq!(format("Paper Book"), Some(Format::Physical));
+        assert_eq!(format("Audible Audio"), Some(Format::Audiobook));
+    }
+}
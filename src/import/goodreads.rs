@@ -0,0 +1,194 @@
+use super::{format, parse_date, read_rows, read_state, split, Export, Row};
+use crate::book::{Book, Contributor, Note, NoteKind, Ownership, Rating, Read, ReadChange, Series};
+use crate::error::Error;
+use crate::isbn::Isbn;
+use std::path::Path;
+
+const DATE_FORMAT: &str = "%Y/%m/%d";
+
+const COLUMNS: [(&str, &str); 18] = [
+    ("Title", "title and series"),
+    ("Author", "authors"),
+    ("Additional Authors", "authors"),
+    ("ISBN", "isbn"),
+    ("ISBN13", "isbn"),
+    ("My Rating", "rating"),
+    ("My Review", "review"),
+    ("Publisher", "publisher"),
+    ("Number of Pages", "pages"),
+    ("Year Published", "year"),
+    ("Original Publication Year", "year"),
+    ("Binding", "formats"),
+    ("Owned Copies", "ownership"),
+    ("Exclusive Shelf", "read state"),
+    ("Bookshelves", "tags"),
+    ("Date Read", "history"),
+    ("Private Notes", "notes"),
+    ("Date Added", "date of notes"),
+];
+
+/// Books from the CSV file of a Goodreads library export
+pub fn goodreads(path: &Path) -> Result<Export, Error> {
+    read_rows(path, b',', &COLUMNS, read_book)
+}
+
+/// Split the series from a title such as "The Two Towers (The Lord of the Rings, #2)"
+fn split_series(title: &str) -> (&str, Option<Series>) {
+    let series = title
+        .strip_suffix(')')
+        .and_then(|t| t.rsplit_once(" ("))
+        .and_then(|(title, series)| Some((title, series.rsplit_once(", #")?)));
+    match series {
+        Some((title, (name, volume))) => (
+            title.trim(),
+            Some(Series {
+                name: name.trim().to_string(),
+                volume: volume.parse().ok(),
+            }),
+        ),
+        None => (title, None),
+    }
+}
+
+/// ISBNs are exported as formulas, `="0141439564"`, to stop spreadsheets taking them as numbers
+fn isbn(row: &Row, column: &str) -> Option<Isbn> {
+    row.get(column)
+        .map(|s| s.trim_start_matches('=').trim_matches('"'))
+        .and_then(|s| s.parse().ok())
+}
+
+fn read_book(row: &Row) -> Result<Book, String> {
+    let title = row.get("Title").ok_or("no title")?;
+    let (title, series) = split_series(title);
+    let mut authors: Vec<Contributor> = row
+        .get("Author")
+        .map(Contributor::from)
+        .into_iter()
+        .collect();
+    for other in split(row.get("Additional Authors"), &[',']) {
+        if let Ok(other) = other.parse() {
+            authors.push(other)
+        }
+    }
+
+    let mut book = Book::new(title.to_string(), authors);
+    book.series = series;
+    book.isbn = isbn(row, "ISBN13").or_else(|| isbn(row, "ISBN"));
+    // Unrated books have a rating of 0
+    book.rating = match row.get("My Rating") {
+        None | Some("0") => None,
+        Some(stars) => Some(
+            stars
+                .parse::<u8>()
+                .ok()
+                .and_then(|s| s.checked_mul(2))
+                .and_then(Rating::from_half_stars)
+                .ok_or_else(|| format!("{} is not a rating", stars))?,
+        ),
+    };
+    book.review = row.get("My Review").map(|r| r.replace("<br/>", "\n"));
+    book.publisher = row.get("Publisher").map(String::from);
+    book.pages = row.parse("Number of Pages");
+    book.year = row
+        .parse("Year Published")
+        .or_else(|| row.parse("Original Publication Year"));
+    book.formats.extend(row.get("Binding").and_then(format));
+    if row.parse::<u32>("Owned Copies").is_some_and(|n| n > 0) {
+        book.ownership = Some(Ownership::Owned);
+    }
+
+    let exclusive = row.get("Exclusive Shelf").unwrap_or("to-read");
+    book.read = read_state(exclusive).unwrap_or_default();
+    // Shelves giving the read state, such as "to-read", are not tags
+    for shelf in split(row.get("Bookshelves"), &[',']).chain([exclusive]) {
+        if read_state(shelf).is_none() {
+            book.tags.insert(shelf.to_string());
+        }
+    }
+
+    if let Some(at) = row
+        .get("Date Read")
+        .and_then(|d| parse_date(d, DATE_FORMAT))
+    {
+        book.history.push(ReadChange {
+            read: Read::Read,
+            at,
+        });
+    }
+    // Notes need a date, so only those with the date the book was added are kept
+    if let (Some(text), Some(at)) = (
+        row.get("Private Notes"),
+        row.get("Date Added")
+            .and_then(|d| parse_date(d, DATE_FORMAT)),
+    ) {
+        book.notes.push(Note {
+            at,
+            kind: NoteKind::Note,
+            page: None,
+            text: text.to_string(),
+        });
+    }
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Format;
+    use crate::import::parse_rows;
+
+    #[test]
+    fn read_export() {
+        let export = parse_rows(
+            "Book Id,Title,Author,Author l-f,Additional Authors,ISBN,ISBN13,My Rating,\
+             Publisher,Binding,Number of Pages,Year Published,Date Read,Date Added,Bookshelves,\
+             Exclusive Shelf,My Review,Owned Copies\n\
+             1,\"The Two Towers (The Lord of the Rings, #2)\",J.R.R. Tolkien,\"Tolkien, J.R.R.\",,\
+             \"=\"\"0618346260\"\"\",\"=\"\"\"\"\",4,Mariner,Paperback,352,2003,2021/03/04,\
+             2020/01/01,\"fantasy, read\",read,Great<br/>book,1\n\
+             2,,Nobody,,,,,0,,,,,,,,to-read,,0\n\
+             3,Beowulf,Unknown,,Seamus Heaney,,,0,,Kindle Edition,,,,,\"to-read, poetry\",to-read,,0\n\
+             4,Dune,Frank Herbert,,,,,0,,Paper,,,,,\"Paused, Favourites\",paused,,0\n\
+             5,Emma,Jane Austen,,,,,200,,,,,,,,read,,0\n",
+            b',',
+            &COLUMNS,
+            read_book,
+        )
+        .unwrap();
+
+        assert_eq!(
+            export.skipped,
+            vec!["line 3: no title", "line 6: 200 is not a rating"]
+        );
+        assert_eq!(export.mapping.ignored, vec!["Book Id", "Author l-f"]);
+        let [towers, beowulf, dune] = &export.books[..] else {
+            panic!("Expected three books, got {:?}", export.books)
+        };
+        assert_eq!(towers.title, "The Two Towers");
+        assert_eq!(
+            towers.series.as_ref().map(|s| s.to_string()),
+            Some("The Lord of the Rings #2".to_string())
+        );
+        assert_eq!(towers.isbn, "0618346260".parse().ok());
+        assert_eq!(towers.rating, Rating::from_half_stars(8));
+        assert_eq!(towers.review.as_deref(), Some("Great\nbook"));
+        assert_eq!(towers.read, Read::Read);
+        assert_eq!(towers.pages, Some(352));
+        assert_eq!(towers.year, Some(2003));
+        assert_eq!(towers.ownership, Some(Ownership::Owned));
+        assert!(towers.formats.contains(&Format::Physical));
+        assert!(towers.contains_tag("fantasy") && towers.tags.len() == 1);
+        assert_eq!(towers.history.len(), 1);
+
+        assert_eq!(beowulf.authors_string(), "Unknown; Seamus Heaney");
+        assert_eq!(beowulf.read, Read::Unread);
+        assert_eq!(beowulf.rating, None);
+        assert!(beowulf.formats.contains(&Format::Ebook));
+        assert!(beowulf.contains_tag("poetry") && beowulf.tags.len() == 1);
+
+        // Shelves giving the read state match whatever their case
+        assert_eq!(dune.read, Read::Stopped);
+        assert!(dune.contains_tag("Favourites") && dune.tags.len() == 1);
+        assert!(dune.formats.contains(&Format::Physical));
+    }
+}
@@ -0,0 +1,341 @@
+use super::{format, parse_date, parse_rows, read_state, read_text, split, Export, Mapping, Row};
+use crate::book::{Book, Contributor, Ownership, Read, ReadChange, Role};
+use crate::error::Error;
+use serde_json::Value;
+use std::path::Path;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+const COLUMNS: [(&str, &str); 18] = [
+    ("Title", "title"),
+    ("Primary Author", "authors"),
+    ("Primary Author Role", "authors"),
+    ("Secondary Author", "authors"),
+    ("Secondary Author Roles", "authors"),
+    ("Publication", "publisher"),
+    ("Date", "year"),
+    ("Review", "review"),
+    ("Rating", "rating"),
+    ("Media", "formats"),
+    ("Page Count", "pages"),
+    ("Date Started", "history"),
+    ("Date Read", "history"),
+    ("Tags", "tags"),
+    ("Collections", "read state and ownership"),
+    ("Languages", "language"),
+    ("ISBN", "isbn"),
+    ("ISBNs", "isbn"),
+];
+
+const KEYS: [(&str, &str); 16] = [
+    ("title", "title"),
+    ("primaryauthor", "authors"),
+    ("primaryauthorrole", "authors"),
+    ("authors", "authors"),
+    ("publication", "publisher"),
+    ("date", "year"),
+    ("review", "review"),
+    ("rating", "rating"),
+    ("format", "formats"),
+    ("pages", "pages"),
+    ("datestarted", "history"),
+    ("dateread", "history"),
+    ("tags", "tags"),
+    ("collections", "read state and ownership"),
+    ("language", "language"),
+    ("isbn", "isbn"),
+];
+
+/// Books from a LibraryThing export, either tab separated or JSON
+pub fn librarything(path: &Path) -> Result<Export, Error> {
+    parse(&read_text(path)?).map_err(|e| Error::Validation(format!("{}: {}", path.display(), e)))
+}
+
+fn parse(text: &str) -> Result<Export, String> {
+    match text.trim_start().starts_with(['{', '[']) {
+        true => parse_json(text),
+        false => parse_rows(text, b'\t', &COLUMNS, |row| read_row(row).into_book()),
+    }
+}
+
+/// Details of a book common to both forms of export
+#[derive(Debug)]
+struct Entry {
+    title: Option<String>,
+    /// Names with their roles
+    authors: Vec<(String, Option<String>)>,
+    publication: Option<String>,
+    date: Option<String>,
+    review: Option<String>,
+    rating: Option<String>,
+    media: Vec<String>,
+    pages: Option<String>,
+    started: Option<String>,
+    read: Option<String>,
+    tags: Vec<String>,
+    collections: Vec<String>,
+    languages: Vec<String>,
+    isbns: Vec<String>,
+}
+
+fn leading_number<T: std::str::FromStr>(s: &str) -> Option<T> {
+    s.trim()
+        .trim_start_matches(|c: char| !c.is_ascii_digit())
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|n| n.parse().ok())
+}
+
+impl Entry {
+    fn into_book(self) -> Result<Book, String> {
+        let title = self.title.ok_or("no title")?;
+        let authors = self
+            .authors
+            .into_iter()
+            .map(|(name, role)| Contributor {
+                name,
+                // Authors are stored without a role
+                role: role
+                    .and_then(|r| r.parse().ok())
+                    .filter(|r| *r != Role::Author),
+            })
+            .collect();
+
+        let mut book = Book::new(title, authors);
+        book.isbn = self
+            .isbns
+            .iter()
+            .find_map(|i| i.trim_matches(['[', ']']).parse().ok());
+        // Publication is given as "Mariner Books (2003), Edition: Reprint, 352 pages"
+        book.publisher = self
+            .publication
+            .as_deref()
+            .and_then(|p| p.split([',', '(']).next())
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(String::from);
+        book.year = self.date.as_deref().and_then(leading_number);
+        book.review = self.review;
+        book.rating = self.rating.and_then(|r| r.parse().ok());
+        book.formats
+            .extend(self.media.iter().filter_map(|m| format(m)));
+        book.pages = self.pages.as_deref().and_then(leading_number);
+        book.tags.extend(self.tags);
+        book.language = self.languages.into_iter().next();
+
+        for collection in &self.collections {
+            match collection.to_lowercase().as_str() {
+                "your library" => book.ownership = Some(Ownership::Owned),
+                "wishlist" => book.ownership = Some(Ownership::Wishlist),
+                c => {
+                    if let Some(read) = read_state(c) {
+                        book.read = read
+                    }
+                }
+            }
+        }
+        for (date, read) in [(&self.started, Read::Reading), (&self.read, Read::Read)] {
+            if let Some(at) = date.as_deref().and_then(|d| parse_date(d, DATE_FORMAT)) {
+                book.history.push(ReadChange {
+                    read: read.clone(),
+                    at,
+                });
+                book.read = read;
+            }
+        }
+        Ok(book)
+    }
+}
+
+fn read_row(row: &Row) -> Entry {
+    let text = |column| row.get(column).map(String::from);
+    let list = |column, separators| -> Vec<String> {
+        split(row.get(column), separators)
+            .map(String::from)
+            .collect()
+    };
+    let mut authors: Vec<_> = text("Primary Author")
+        .map(|a| (a, text("Primary Author Role")))
+        .into_iter()
+        .collect();
+    let roles = list("Secondary Author Roles", &['|']);
+    for (i, name) in list("Secondary Author", &['|']).into_iter().enumerate() {
+        authors.push((name, roles.get(i).cloned()));
+    }
+    Entry {
+        title: text("Title"),
+        authors,
+        publication: text("Publication"),
+        date: text("Date"),
+        review: text("Review"),
+        rating: text("Rating"),
+        media: list("Media", &[',']),
+        pages: text("Page Count"),
+        started: text("Date Started"),
+        read: text("Date Read"),
+        tags: list("Tags", &[',']),
+        collections: list("Collections", &[',']),
+        languages: list("Languages", &[',']),
+        isbns: [text("ISBN")]
+            .into_iter()
+            .flatten()
+            .chain(list("ISBNs", &[',']))
+            .collect(),
+    }
+}
+
+/// Text in a JSON value, which may be a string, a list, or objects such as formats
+fn strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(s)) if !s.trim().is_empty() => vec![s.trim().to_string()],
+        Some(Value::Number(n)) => vec![n.to_string()],
+        Some(Value::Array(values)) => values.iter().flat_map(|v| strings(Some(v))).collect(),
+        Some(Value::Object(o)) => match o.get("text") {
+            Some(text) => strings(Some(text)),
+            None => o.values().flat_map(|v| strings(Some(v))).collect(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn read_object(book: &serde_json::Map<String, Value>) -> Entry {
+    let text = |key| strings(book.get(key)).into_iter().next();
+    let mut authors: Vec<_> = match book.get("authors") {
+        Some(Value::Array(authors)) => authors
+            .iter()
+            .filter_map(|a| {
+                let name = text_of(a, "fl").or_else(|| text_of(a, "lf"))?;
+                Some((name, text_of(a, "role")))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if authors.is_empty() {
+        authors.extend(text("primaryauthor").map(|a| (a, text("primaryauthorrole"))));
+    }
+    Entry {
+        title: text("title"),
+        authors,
+        publication: text("publication"),
+        date: text("date"),
+        review: text("review"),
+        rating: text("rating"),
+        media: strings(book.get("format")),
+        pages: text("pages"),
+        started: text("datestarted"),
+        read: text("dateread"),
+        tags: strings(book.get("tags")),
+        collections: strings(book.get("collections")),
+        languages: strings(book.get("language")),
+        isbns: strings(book.get("isbn")),
+    }
+}
+
+fn text_of(value: &Value, key: &str) -> Option<String> {
+    strings(value.get(key)).into_iter().next()
+}
+
+/// The JSON export is an object of books by their LibraryThing id
+fn parse_json(text: &str) -> Result<Export, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let books: Vec<(String, &Value)> = match &value {
+        Value::Object(books) => books.iter().map(|(id, b)| (id.clone(), b)).collect(),
+        Value::Array(books) => books
+            .iter()
+            .enumerate()
+            .map(|(i, b)| ((i + 1).to_string(), b))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let mut keys: Vec<&str> = Vec::new();
+    let mut export = Export::default();
+    for (id, book) in books {
+        let Value::Object(book) = book else {
+            export.skipped.push(format!("book {}: not an object", id));
+            continue;
+        };
+        for key in book.keys() {
+            if !keys.contains(&key.as_str()) {
+                keys.push(key)
+            }
+        }
+        match read_object(book).into_book() {
+            Ok(book) => export.books.push(book),
+            Err(e) => export.skipped.push(format!("book {}: {}", id, e)),
+        }
+    }
+    export.mapping = Mapping::new(keys, &KEYS);
+    Ok(export)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{Format, Rating};
+
+    #[test]
+    fn read_tsv() {
+        let export = parse(
+            "Book Id\tTitle\tPrimary Author\tPrimary Author Role\tSecondary Author\t\
+             Secondary Author Roles\tPublication\tDate\tRating\tMedia\tPage Count\tDate Read\t\
+             Tags\tCollections\tISBN\tBCID\n\
+             1\tThe Two Towers\tTolkien, J.R.R.\tAuthor\tLee, Alan\tIllustrator\t\
+             Mariner Books (2003), Edition: Reprint, 352 pages\t2003\t4.5\tPaper Book\t352\t\
+             2021-03-04\tfantasy, classic\tYour library\t[0618346260]\t\n",
+        )
+        .unwrap();
+        assert!(export.skipped.is_empty());
+        assert_eq!(export.mapping.ignored, vec!["Book Id", "BCID"]);
+        let [towers] = &export.books[..] else {
+            panic!("Expected one book, got {:?}", export.books)
+        };
+        assert_eq!(
+            towers.authors_string(),
+            "Tolkien, J.R.R.; Lee, Alan (Illustrator)"
+        );
+        assert_eq!(towers.publisher.as_deref(), Some("Mariner Books"));
+        assert_eq!(towers.year, Some(2003));
+        assert_eq!(towers.rating, Rating::from_half_stars(9));
+        assert_eq!(towers.pages, Some(352));
+        assert_eq!(towers.read, Read::Read);
+        assert_eq!(towers.ownership, Some(Ownership::Owned));
+        assert!(towers.formats.contains(&Format::Physical));
+        assert!(towers.contains_tag("classic"));
+        assert_eq!(towers.isbn, "0618346260".parse().ok());
+    }
+
+    #[test]
+    fn read_json() {
+        let export = parse(
+            r#"{
+                "101": {
+                    "books_id": "101",
+                    "title": "Beowulf",
+                    "authors": [
+                        {"lf": "Heaney, Seamus", "fl": "Seamus Heaney", "role": "Translator"}
+                    ],
+                    "date": "2001",
+                    "format": [{"code": "EB", "text": "Ebook"}],
+                    "collections": ["Currently reading", "Wishlist"],
+                    "isbn": {"0": "0393320979", "2": "9780393320978"},
+                    "language": ["English"]
+                },
+                "102": {"books_id": "102", "primaryauthor": "Nobody"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(export.skipped, vec!["book 102: no title"]);
+        assert_eq!(export.mapping.ignored, vec!["books_id"]);
+        let [beowulf] = &export.books[..] else {
+            panic!("Expected one book, got {:?}", export.books)
+        };
+        assert_eq!(beowulf.authors_string(), "Seamus Heaney (Translator)");
+        assert_eq!(beowulf.year, Some(2001));
+        assert_eq!(beowulf.read, Read::Reading);
+        assert_eq!(beowulf.ownership, Some(Ownership::Wishlist));
+        assert!(beowulf.formats.contains(&Format::Ebook));
+        assert_eq!(beowulf.language.as_deref(), Some("English"));
+        assert_eq!(beowulf.isbn, "9780393320978".parse().ok());
+    }
+}
@@ -0,0 +1,112 @@
+use super::{format, parse_date, read_rows, read_state, split, Export, Row};
+use crate::book::{Book, Contributor, Ownership, Read, ReadChange};
+use crate::error::Error;
+use std::path::Path;
+
+const DATE_FORMAT: &str = "%Y/%m/%d";
+
+const COLUMNS: [(&str, &str); 11] = [
+    ("Title", "title"),
+    ("Authors", "authors"),
+    ("Contributors", "authors"),
+    ("ISBN/UID", "isbn"),
+    ("Format", "formats"),
+    ("Read Status", "read state"),
+    ("Last Date Read", "history"),
+    ("Star Rating", "rating"),
+    ("Review", "review"),
+    ("Tags", "tags"),
+    ("Owned?", "ownership"),
+];
+
+/// Books from the CSV file of a StoryGraph export
+pub fn storygraph(path: &Path) -> Result<Export, Error> {
+    read_rows(path, b',', &COLUMNS, read_book)
+}
+
+fn read_book(row: &Row) -> Result<Book, String> {
+    let title = row.get("Title").ok_or("no title")?;
+    let mut authors: Vec<Contributor> = split(row.get("Authors"), &[','])
+        .map(Contributor::from)
+        .collect();
+    // Contributors are given with their role, as "Name (Translator)"
+    for other in split(row.get("Contributors"), &[',']) {
+        authors.push(other.parse().unwrap_or_else(|_| {
+            Contributor::from(other.rsplit_once(" (").map_or(other, |(name, _)| name))
+        }));
+    }
+
+    let mut book = Book::new(title.to_string(), authors);
+    book.isbn = row.parse("ISBN/UID");
+    book.formats.extend(row.get("Format").and_then(format));
+    book.read = row
+        .get("Read Status")
+        .and_then(read_state)
+        .unwrap_or_default();
+    if let Some(at) = row
+        .get("Last Date Read")
+        .and_then(|d| parse_date(d, DATE_FORMAT))
+    {
+        book.history.push(ReadChange {
+            read: Read::Read,
+            at,
+        });
+    }
+    book.rating = row.parse("Star Rating");
+    book.review = row.get("Review").map(String::from);
+    book.tags
+        .extend(split(row.get("Tags"), &[',']).map(String::from));
+    if row
+        .get("Owned?")
+        .is_some_and(|o| o.eq_ignore_ascii_case("yes"))
+    {
+        book.ownership = Some(Ownership::Owned);
+    }
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{Format, Rating, Role};
+    use crate::import::parse_rows;
+
+    #[test]
+    fn read_export() {
+        let export = parse_rows(
+            "Title,Authors,Contributors,ISBN/UID,Format,Read Status,Date Added,Last Date Read,\
+             Dates Read,Read Count,Moods,Pace,Star Rating,Review,Tags,Owned?\n\
+             Don Quixote,Miguel de Cervantes,\"Edith Grossman (Translator), Someone (Blurb)\",\
+             9780060934347,paperback,read,2023/01/02,2023/05/12,2023/05/01-2023/05/12,1,\
+             adventurous,slow,4.5,Long,\"classics, spain\",Yes\n\
+             Piranesi,Susanna Clarke,,,audio,did-not-finish,2023/01/02,,,0,,,,,,No\n",
+            b',',
+            &COLUMNS,
+            read_book,
+        )
+        .unwrap();
+
+        assert!(export.skipped.is_empty());
+        assert_eq!(
+            export.mapping.ignored,
+            vec!["Date Added", "Dates Read", "Read Count", "Moods", "Pace"]
+        );
+        let [quixote, piranesi] = &export.books[..] else {
+            panic!("Expected two books, got {:?}", export.books)
+        };
+        assert_eq!(quixote.authors.len(), 3);
+        assert_eq!(quixote.authors[1].role, Some(Role::Translator));
+        assert_eq!(quixote.authors[2].name, "Someone");
+        assert_eq!(quixote.isbn, "9780060934347".parse().ok());
+        assert_eq!(quixote.read, Read::Read);
+        assert_eq!(quixote.rating, Rating::from_half_stars(9));
+        assert_eq!(quixote.history.len(), 1);
+        assert_eq!(quixote.tags.len(), 2);
+        assert_eq!(quixote.ownership, Some(Ownership::Owned));
+        assert!(quixote.formats.contains(&Format::Physical));
+
+        assert_eq!(piranesi.read, Read::Stopped);
+        assert_eq!(piranesi.ownership, None);
+        assert!(piranesi.formats.contains(&Format::Audiobook));
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// An ISBN, validated and normalised to ISBN-13
+#[derive(Eq, PartialEq, Debug, Clone, Hash, Deserialize, Serialize)]
+#[serde(try_from = "IsbnRepr", into = "String")]
+pub struct Isbn(String);
+
+/// ISBN-13s may be written as plain numbers in YAML
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IsbnRepr {
+    Number(u64),
+    Text(String),
+}
+
+impl TryFrom<IsbnRepr> for Isbn {
+    type Error = String;
+
+    fn try_from(repr: IsbnRepr) -> Result<Self, Self::Error> {
+        match repr {
+            IsbnRepr::Number(n) => n.to_string().parse(),
+            IsbnRepr::Text(s) => s.parse(),
+        }
+    }
+}
+
+impl From<Isbn> for String {
+    fn from(isbn: Isbn) -> String {
+        isbn.0
+    }
+}
+
+fn isbn10_check_digit(digits: &[u32]) -> char {
+    let sum: u32 = zip_weights(digits, (2..=10).rev());
+    match (11 - sum % 11) % 11 {
+        10 => 'X',
+        d => char::from_digit(d, 10).expect("check digit below 10"),
+    }
+}
+
+fn isbn13_check_digit(digits: &[u32]) -> char {
+    let sum: u32 = zip_weights(digits, [1, 3].into_iter().cycle());
+    char::from_digit((10 - sum % 10) % 10, 10).expect("check digit below 10")
+}
+
+fn zip_weights(digits: &[u32], weights: impl Iterator<Item = u32>) -> u32 {
+    digits.iter().zip(weights).map(|(d, w)| d * w).sum()
+}
+
+fn digits(s: &str) -> Option<Vec<u32>> {
+    s.chars().map(|c| c.to_digit(10)).collect()
+}
+
+impl Isbn {
+    /// The ISBN-13 form, without hyphens
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// The ISBN-10 form, which only exists for ISBNs with the 978 prefix
+    pub fn to_isbn10(&self) -> Option<String> {
+        let body = self.0.strip_prefix("978")?.get(..9)?;
+        let check = isbn10_check_digit(&digits(body)?);
+        Some(format!("{}{}", body, check))
+    }
+}
+
+impl fmt::Display for Isbn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses ISBN-10 or ISBN-13, ignoring hyphens and spaces and checking the check digit
+impl FromStr for Isbn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let compact: String = s
+            .chars()
+            .filter(|c| !matches!(c, '-' | ' '))
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let invalid = || format!("{} is not a valid ISBN", s.trim());
+        if !compact.chars().all(|c| c.is_ascii_digit() || c == 'X') {
+            return Err(invalid());
+        }
+        match compact.len() {
+            10 => {
+                let (body, check) = compact.split_at(9);
+                let body_digits = digits(body).ok_or_else(invalid)?;
+                if !check.starts_with(isbn10_check_digit(&body_digits)) {
+                    return Err(invalid());
+                }
+                let body = format!("978{}", body);
+                let check = isbn13_check_digit(&digits(&body).ok_or_else(invalid)?);
+                Ok(Isbn(format!("{}{}", body, check)))
+            }
+            13 => {
+                let (body, check) = compact.split_at(12);
+                let body_digits = digits(body).ok_or_else(invalid)?;
+                match check.starts_with(isbn13_check_digit(&body_digits)) {
+                    true => Ok(Isbn(compact)),
+                    false => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let isbn: Isbn = "978-0-14-143956-3".parse().unwrap();
+        assert_eq!(isbn.as_str(), "9780141439563");
+        assert_eq!("0-14-143956-4".parse(), Ok(isbn.clone()));
+        assert_eq!(isbn.to_isbn10(), Some("0141439564".to_string()));
+
+        let isbn: Isbn = "0-8044-2957-x".parse().unwrap();
+        assert_eq!(isbn.as_str(), "9780804429573");
+        assert_eq!(isbn.to_isbn10(), Some("080442957X".to_string()));
+
+        assert!("978-0-14-143956-4".parse::<Isbn>().is_err());
+        assert!("0-14-143956-3".parse::<Isbn>().is_err());
+        assert!("12345".parse::<Isbn>().is_err());
+        assert!("12345678é".parse::<Isbn>().is_err());
+        assert!("978014143956é".parse::<Isbn>().is_err());
+        assert_eq!(
+            "979-10-90636-07-1".parse::<Isbn>().unwrap().to_isbn10(),
+            None
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        let isbn: Isbn = serde_yaml::from_str("9780141439563").unwrap();
+        assert_eq!(isbn.as_str(), "9780141439563");
+        let isbn: Isbn = serde_yaml::from_str("'0141439564'").unwrap();
+        assert_eq!(isbn.as_str(), "9780141439563");
+        assert!(serde_yaml::from_str::<Isbn>("'0141439563'").is_err());
+        assert_eq!(
+            serde_yaml::to_string(&isbn).unwrap(),
+            "'9780141439563'\n".to_string()
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::author::Author;
+use crate::backup;
+use crate::book::Book;
+use crate::error::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Number of operations kept for undoing
+const JOURNAL_LENGTH: usize = 100;
+
+/// A book before and after an operation, `None` where it did not exist
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub id: usize,
+    pub before: Option<Book>,
+    pub after: Option<Book>,
+}
+
+/// A list kept by the bookcase, such as its shelves, before and after an operation
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ListChange<T> {
+    pub before: Vec<T>,
+    pub after: Vec<T>,
+}
+
+impl<T: Clone + PartialEq> ListChange<T> {
+    fn new(before: &[T], after: &[T]) -> Option<ListChange<T>> {
+        (before != after).then(|| ListChange {
+            before: before.to_vec(),
+            after: after.to_vec(),
+        })
+    }
+    fn apply(&self, list: &mut Vec<T>, undo: bool) {
+        *list = match undo {
+            true => self.before.clone(),
+            false => self.after.clone(),
+        }
+    }
+}
+
+/// The parts of a bookcase which are journalled
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct State {
+    pub books: BTreeMap<usize, Book>,
+    pub shelves: Vec<String>,
+    pub authors: Vec<Author>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub at: DateTime<Utc>,
+    pub description: String,
+    pub changes: Vec<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shelves: Option<ListChange<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authors: Option<ListChange<Author>>,
+}
+
+impl Entry {
+    /// Set each changed part to its state before (when undoing) or after the operation
+    fn apply(&self, state: &mut State, undo: bool) {
+        for change in &self.changes {
+            let book = match undo {
+                true => &change.before,
+                false => &change.after,
+            };
+            match book {
+                Some(book) => {
+                    let mut book = book.clone();
+                    // Books journalled before they were saved with a UUID keep the one given
+                    if book.uuid.is_none() {
+                        book.uuid = state.books.get(&change.id).and_then(|b| b.uuid);
+                    }
+                    state.books.insert(change.id, book)
+                }
+                None => state.books.remove(&change.id),
+            };
+        }
+        if let Some(shelves) = &self.shelves {
+            shelves.apply(&mut state.shelves, undo)
+        }
+        if let Some(authors) = &self.authors {
+            authors.apply(&mut state.authors, undo)
+        }
+    }
+}
+
+/// Operations on a bookcase which can be undone, and those undone which can be redone
+#[derive(Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(default)]
+    done: Vec<Entry>,
+    #[serde(default)]
+    undone: Vec<Entry>,
+}
+
+impl Journal {
+    /// The journal is kept next to the bookcase file
+    pub fn path(bookcase: &Path) -> PathBuf {
+        backup::with_suffix(bookcase, ".journal")
+    }
+    pub fn open(path: &Path) -> Result<Journal, Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_yaml::from_str(&contents).map_err(|e| Error::parse(path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Journal::default()),
+            Err(e) => Err(Error::io(path, e)),
+        }
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if self.done.is_empty() && self.undone.is_empty() && !path.exists() {
+            return Ok(());
+        }
+        let contents = serde_yaml::to_string(self).map_err(|e| Error::serialise(path, e))?;
+        backup::write_atomic(path, contents.as_bytes())
+    }
+    /// Record the changes between two states of a bookcase, returning whether there were any
+    pub fn record(&mut self, description: &str, before: &State, after: &State) -> bool {
+        let changes = diff(&before.books, &after.books);
+        let shelves = ListChange::new(&before.shelves, &after.shelves);
+        let authors = ListChange::new(&before.authors, &after.authors);
+        if changes.is_empty() && shelves.is_none() && authors.is_none() {
+            return false;
+        }
+        self.done.push(Entry {
+            at: Utc::now(),
+            description: description.to_string(),
+            changes,
+            shelves,
+            authors,
+        });
+        if self.done.len() > JOURNAL_LENGTH {
+            self.done.drain(..self.done.len() - JOURNAL_LENGTH);
+        }
+        self.undone.clear();
+        true
+    }
+    pub fn undo(&mut self, state: &mut State) -> Option<&Entry> {
+        let entry = self.done.pop()?;
+        entry.apply(state, true);
+        self.undone.push(entry);
+        self.undone.last()
+    }
+    pub fn redo(&mut self, state: &mut State) -> Option<&Entry> {
+        let entry = self.undone.pop()?;
+        entry.apply(state, false);
+        self.done.push(entry);
+        self.done.last()
+    }
+}
+
+fn diff(before: &BTreeMap<usize, Book>, after: &BTreeMap<usize, Book>) -> Vec<Change> {
+    let mut ids: Vec<usize> = before.keys().chain(after.keys()).copied().collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids.into_iter()
+        .filter(|id| before.get(id) != after.get(id))
+        .map(|id| Change {
+            id,
+            before: before.get(&id).cloned(),
+            after: after.get(&id).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Read;
+
+    #[test]
+    fn undo_redo() {
+        let mut journal = Journal::default();
+        let before = State {
+            books: BTreeMap::from([
+                (1, Book::new("One".to_string(), vec![])),
+                (2, Book::new("Two".to_string(), vec![])),
+            ]),
+            shelves: vec!["Attic".to_string()],
+            authors: Vec::new(),
+        };
+        let mut state = before.clone();
+        state.books.remove(&1);
+        state.books.get_mut(&2).unwrap().start();
+        state
+            .books
+            .insert(3, Book::new("Three".to_string(), vec![]));
+        let after = state.clone();
+
+        assert!(journal.record("edit", &before, &after));
+        assert!(!journal.record("nothing", &after, &after));
+        assert_eq!(journal.done[0].changes.len(), 3);
+
+        assert_eq!(journal.undo(&mut state).unwrap().description, "edit");
+        assert_eq!(state, before);
+        assert!(journal.undo(&mut state).is_none());
+
+        journal.redo(&mut state);
+        assert_eq!(state, after);
+        assert!(journal.redo(&mut state).is_none());
+
+        // Shelves and authors are journalled even when no book changes
+        state.shelves.push("Hall".to_string());
+        state.authors.push(Author::new("Charles Dickens"));
+        assert!(journal.record("shelf create Hall", &after, &state));
+        assert_eq!(journal.done[1].changes, vec![]);
+        journal.undo(&mut state);
+        assert_eq!(state, after);
+
+        // A book given a UUID when saved keeps it when an earlier change is undone
+        let mut before = State::default();
+        before.books.insert(1, Book::default());
+        let mut after = before.clone();
+        after.books.get_mut(&1).unwrap().start();
+        assert!(journal.record("start 1", &before, &after));
+        let uuid = Some(uuid::Uuid::new_v4());
+        let mut state = after.clone();
+        state.books.get_mut(&1).unwrap().uuid = uuid;
+        journal.undo(&mut state);
+        assert_eq!(state.books[&1].uuid, uuid);
+        assert_eq!(state.books[&1].read_state(), &Read::Unread);
+    }
+}
@@ -1,9 +1,24 @@
+#![allow(mixed_script_confusables)]
 use clap::{Args, Parser, Subcommand};
-use std::fs::File;
-use std::path::PathBuf;
+use error::Error;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+mod author;
+mod backup;
 mod book;
 mod books;
+mod check;
+mod dedupe;
+mod error;
+mod field;
 mod filter;
+mod import;
+mod isbn;
+mod journal;
+mod merge;
+mod migrate;
+mod stats;
 mod tui;
 mod util;
 
@@ -11,8 +26,8 @@ mod util;
 #[command(about = "A basic tracker for books", long_about = None)]
 struct Cli {
     #[arg(long, short)]
-    /// File containing existing bookcase
-    file: Option<PathBuf>,
+    /// File containing existing bookcase, or a glob; several are combined read-only
+    file: Vec<PathBuf>,
 
     #[arg(long, num_args = 0)]
     /// Do not attempt to open a (default) file
@@ -26,6 +41,14 @@ struct Cli {
     /// Follow command with list
     list: bool,
 
+    #[arg(long, global = true)]
+    /// Only use books on a shelf, adding new books to it
+    shelf: Option<String>,
+
+    #[arg(long, default_value_t = backup::DEFAULT_BACKUPS)]
+    /// Number of backups of the file to keep when saving
+    backups: usize,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -33,29 +56,226 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// Add a book
-    Add { title: String, author: String },
+    Add {
+        title: String,
+        #[arg(required = true, value_parser = str::parse::<book::Contributor>)]
+        /// Authors and other contributors, as `Name` or `Name (Role)`
+        authors: Vec<book::Contributor>,
+        #[arg(long)]
+        /// Number of pages in the book
+        pages: Option<u32>,
+        #[arg(long)]
+        /// Series the book is part of
+        series: Option<String>,
+        #[arg(long, requires = "series")]
+        /// Volume number within the series
+        volume: Option<book::Volume>,
+        #[arg(long)]
+        /// ISBN-10 or ISBN-13
+        isbn: Option<isbn::Isbn>,
+        #[arg(long)]
+        publisher: Option<String>,
+        #[arg(long)]
+        /// Year of publication
+        year: Option<i32>,
+        #[arg(long)]
+        language: Option<String>,
+        #[arg(long)]
+        edition: Option<String>,
+        #[arg(long)]
+        /// Formats of the book (physical, ebook, audiobook)
+        format: Vec<book::Format>,
+        #[arg(long)]
+        /// Whether the book is owned, ordered, borrowed, from a library or wishlisted
+        ownership: Option<book::Ownership>,
+    },
     /// List all books
-    List {},
+    List {
+        #[arg(long)]
+        /// Only list books with a field, given as `name` or `name=value`
+        field: Vec<field::FieldFilter>,
+        #[arg(long)]
+        /// Only list books in a format
+        format: Vec<book::Format>,
+        #[arg(long)]
+        /// Only list books with an ownership
+        ownership: Vec<book::Ownership>,
+        #[arg(long)]
+        /// Sort by title, author, rating, series or field:<name>
+        sort: Option<book::Sorting>,
+        #[arg(long, num_args = 0)]
+        /// Show the UUID of each book
+        uuids: bool,
+    },
     /// Initialise bookcase file
     Init { path: PathBuf },
     /// Remove book
-    Remove { id: usize },
+    Remove { id: books::BookRef },
     /// Pick a book at random
     Pick {},
+    /// Suggest the next unread volume of each series being read
+    Next {},
+    /// Summarise the books read
+    Stats {},
     /// Start reading a book
-    Start { id: usize },
+    Start { id: books::BookRef },
     /// Finish reading a book
-    Finish { id: usize },
+    Finish {
+        id: books::BookRef,
+        #[arg(long)]
+        /// Rating out of 5 stars, in half stars
+        rating: Option<book::Rating>,
+        #[arg(long, num_args = 0..=1, default_missing_value = "")]
+        /// Review of the book, opening $EDITOR if no review is given
+        review: Option<String>,
+    },
     /// Pause reading a book
-    Stop { id: usize },
+    Stop { id: books::BookRef },
     /// Return a book to unread
-    Reset { id: usize },
+    Reset { id: books::BookRef },
+    /// Update progress through a book, as a page number or a percentage
+    Progress {
+        id: books::BookRef,
+        progress: book::Progress,
+        #[arg(long)]
+        /// Number of pages in the book
+        pages: Option<u32>,
+    },
+    /// Set or clear the series a book is part of
+    Series {
+        id: books::BookRef,
+        /// Series name, clearing the series if not given
+        name: Option<String>,
+        #[arg(long, requires = "name")]
+        /// Volume number within the series
+        volume: Option<book::Volume>,
+    },
+    /// Set the formats of a book (physical, ebook, audiobook)
+    Formats {
+        id: books::BookRef,
+        formats: Vec<book::Format>,
+    },
+    /// Set or clear whether a book is owned, ordered, borrowed, from a library or wishlisted
+    Ownership {
+        id: books::BookRef,
+        ownership: Option<book::Ownership>,
+    },
+    /// Lend a book to someone
+    Lend {
+        id: books::BookRef,
+        borrower: String,
+        #[arg(long)]
+        /// Date the book is due back (YYYY-MM-DD)
+        due: Option<chrono::NaiveDate>,
+    },
+    /// Record a lent book as returned
+    Return { id: books::BookRef },
+    /// List books which are lent out
+    Loans {},
+    /// Add a note or quote to a book, from stdin or $EDITOR
+    Note {
+        id: books::BookRef,
+        #[arg(long, num_args = 0)]
+        /// Record a quotation rather than a note
+        quote: bool,
+        #[arg(long)]
+        /// Page the note refers to
+        page: Option<u32>,
+    },
+    /// List quotes from one or all books
+    Quotes { id: Option<books::BookRef> },
+    /// Set a custom field on a book
+    Set {
+        id: books::BookRef,
+        name: String,
+        value: String,
+        #[arg(long = "type")]
+        /// Type of the value (bool, number, date or string), inferred if not given
+        field_type: Option<field::FieldType>,
+    },
+    /// Remove a custom field from a book
+    Unset { id: books::BookRef, name: String },
+    /// Manage shelves of books
+    Shelf(Shelf),
+    /// Import books from another program's export, merging any already in the bookcase
+    Import(Import),
+    /// Undo the last change to the bookcase
+    Undo {},
+    /// Redo the last change undone
+    Redo {},
     /// Use a utility function
     Util(Util),
     /// Start UI
     Tui { file: Option<PathBuf> },
 }
 
+impl Commands {
+    /// Whether the command only reads the bookcase, so can work on several combined
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Commands::List { .. }
+                | Commands::Pick {}
+                | Commands::Next {}
+                | Commands::Stats {}
+                | Commands::Loans {}
+                | Commands::Quotes { .. }
+                | Commands::Tui { file: None }
+                | Commands::Shelf(Shelf {
+                    command: ShelfCommands::List
+                })
+        )
+    }
+}
+
+#[derive(Debug, Args)]
+struct Import {
+    #[command(subcommand)]
+    command: ImportCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum ImportCommands {
+    /// Library export from Goodreads, as CSV
+    ///
+    /// The exclusive shelf gives whether a book is read, and other shelves become tags.
+    Goodreads { path: PathBuf },
+    /// Export from StoryGraph, as CSV
+    Storygraph { path: PathBuf },
+    /// Export from LibraryThing, as tab separated values or JSON
+    Librarything { path: PathBuf },
+}
+
+#[derive(Debug, Args)]
+struct Shelf {
+    #[command(subcommand)]
+    command: ShelfCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum ShelfCommands {
+    /// List shelves with the number of books on each
+    List,
+    /// Create an empty shelf
+    Create { name: String },
+    /// Delete a shelf, taking its books off it
+    Delete { name: String },
+    /// Rename a shelf
+    Rename { name: String, new_name: String },
+    /// Put books on a shelf, creating it if needed
+    Add {
+        name: String,
+        #[arg(required = true)]
+        ids: Vec<books::BookRef>,
+    },
+    /// Take books off a shelf
+    Remove {
+        name: String,
+        #[arg(required = true)]
+        ids: Vec<books::BookRef>,
+    },
+}
+
 #[derive(Debug, Args)]
 struct Util {
     #[command(subcommand)]
@@ -71,89 +291,878 @@ enum UtilCommands {
     ExampleBookcase,
     /// Re-index bookcase, reassigning no longer active keys
     Renumber,
+    /// Upgrade the file to the current schema version, saving it with `util --write`
+    Migrate {
+        #[arg(long)]
+        /// Only report what would change, without writing
+        check: bool,
+    },
+    /// Find duplicate books and merge each into the first of them
+    ///
+    /// Only books with the same ISBN, or the same title and author, are merged. Books which
+    /// are merely similar are listed, to be merged with `--merge` once checked.
+    Dedupe {
+        #[arg(long, default_value_t = dedupe::DEFAULT_SIMILARITY)]
+        /// Similarity of title and authors, from 0 to 1, for books to count as possible duplicates
+        similarity: f64,
+        #[arg(long, num_args = 2, value_names = ["KEEP", "DUPLICATE"])]
+        /// Merge one book into another, rather than finding duplicates
+        merge: Vec<books::BookRef>,
+    },
+    /// Three-way merge of diverged bookcase files, printing the result
+    ///
+    /// To let git merge bookcases, set `*.booktop.yaml merge=booktop` in .gitattributes and
+    /// `git config merge.booktop.driver "booktop --no-file util merge --driver %O %A %B"`.
+    Merge {
+        /// Common ancestor of the two versions
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+        #[arg(short, long)]
+        /// Write the result to a file instead
+        output: Option<PathBuf>,
+        #[arg(long, num_args = 0)]
+        /// Write the result over ours without asking about conflicts, as a git merge driver
+        driver: bool,
+    },
+    /// List backups of the file, or load one to restore it
+    Restore {
+        /// Number of the backup to restore, as listed
+        number: Option<usize>,
+    },
+    /// Report problems in the file, such as empty titles or invalid ISBNs
+    ///
+    /// Exits with an error if problems are left, for use in CI or git hooks.
+    Check {
+        #[arg(long)]
+        /// Apply safe corrections, such as trimming whitespace, saving them with `util --write`
+        fix: bool,
+        #[arg(long, default_value_t)]
+        /// Report format: text or json
+        format: check::Output,
+    },
+    /// List authors, or merge names of the same author
+    Authors {
+        #[command(subcommand)]
+        command: Option<AuthorCommands>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum AuthorCommands {
+    /// List authors with their books, aliases and names which may be the same author
+    List,
+    /// Make other names aliases of an author, renaming them in books
+    Merge {
+        name: String,
+        #[arg(required = true)]
+        aliases: Vec<String>,
+    },
+    /// Set the name an author sorts by, or clear it to sort by surname
+    SortName {
+        name: String,
+        sort_name: Option<String>,
+    },
 }
 
 fn list(books: &books::Bookcase) {
+    list_filtered(books, &filter::Filter::default(), None, false)
+}
+
+fn list_filtered(
+    books: &books::Bookcase,
+    filter: &filter::Filter,
+    sorting: Option<&book::Sorting>,
+    uuids: bool,
+) {
     println!("Bookcase: {}", books.name);
     println!("========================================");
-    for (id, bk) in books.get_books() {
-        println!("{}: {}", id, bk);
+    let mut matches: Vec<_> = filter
+        .filter_books(books.get_books().into_iter().collect())
+        .collect();
+    if let Some(sorting) = sorting {
+        matches.sort_by(|(_, b1), (_, b2)| books.cmp_books(b1, b2, sorting));
+    }
+    for (id, bk) in matches {
+        let source = bk
+            .source
+            .as_ref()
+            .map(|s| format!(" [{}]", s))
+            .unwrap_or_default();
+        match bk.read_count() {
+            0 => println!("{}: {}{}", id, bk, source),
+            1 => println!("{}: {} read once{}", id, bk, source),
+            n => println!("{}: {} read {} times{}", id, bk, n, source),
+        }
+        if let (true, Some(uuid)) = (uuids, bk.uuid) {
+            println!("    UUID: {}", uuid);
+        }
+        let details = bk.details();
+        if !details.is_empty() {
+            println!("    {}", details.join(", "));
+        }
+        match (&bk.ownership, bk.formats.is_empty()) {
+            (Some(o), false) => println!("    {}: {}", o, bk.formats_string()),
+            (Some(o), true) => println!("    {}", o),
+            (None, false) => println!("    {}", bk.formats_string()),
+            (None, true) => {}
+        }
+        for (name, value) in &bk.fields {
+            println!("    {}: {}", name, value);
+        }
+        for change in bk.history() {
+            println!("    {}", change);
+        }
+    }
+}
+
+fn loans(books: &books::Bookcase, shelf: Option<&str>) {
+    let today = chrono::Local::now().date_naive();
+    let highlight = std::io::stdout().is_terminal();
+    let selected = books
+        .get_loans()
+        .into_iter()
+        .filter(|(_, b, _)| shelf.is_none_or(|s| b.is_on_shelf(s)));
+    for (id, bk, loan) in selected {
+        let line = format!("{}: {} | {}", id, bk.title, loan);
+        match (loan.is_overdue(today), highlight) {
+            (true, true) => println!("\x1b[31m{} (overdue)\x1b[0m", line),
+            (true, false) => println!("{} (overdue)", line),
+            (false, _) => println!("{}", line),
+        }
+    }
+}
+
+fn quotes(books: &books::Bookcase, id: Option<usize>, shelf: Option<&str>) {
+    let selected = books
+        .get_books()
+        .into_iter()
+        .filter(|(k, _)| id.is_none_or(|id| **k == id))
+        .filter(|(_, b)| shelf.is_none_or(|s| b.is_on_shelf(s)));
+    for (id, bk) in selected {
+        for quote in bk.quotes() {
+            println!("{}: {} | {}", id, bk.title, quote.heading());
+            for line in quote.text.lines() {
+                println!("    {}", line);
+            }
+        }
+    }
+}
+
+fn authors(books: &books::Bookcase) {
+    for name in books.get_authors() {
+        let count = books
+            .get_books()
+            .into_iter()
+            .filter(|(_, b)| {
+                b.contributor_names()
+                    .any(|n| books.canonical_author(n) == name)
+            })
+            .count();
+        println!("{} [{}] ({})", name, books.author_sort_name(&name), count);
+        if let Some(a) = books.authors.iter().find(|a| a.name == name) {
+            if !a.aliases.is_empty() {
+                println!("    also {}", a.aliases.join(", "));
+            }
+        }
+    }
+    for (a, b) in books.similar_authors() {
+        println!(
+            "{} may be {}: `booktop util -w authors merge \"{}\" \"{}\"`",
+            a, b, b, a
+        );
+    }
+}
+
+fn restore_list(path: &Path) {
+    let backups = backup::list(path);
+    if backups.is_empty() {
+        println!("No backups of {}", path.display());
+    }
+    for b in backups {
+        let modified = b
+            .modified
+            .map(|m| m.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let count = books::Bookcase::open(&b.path)
+            .map(|books| format!("{} books", books.books.len()))
+            .unwrap_or_else(|e| e.to_string());
+        println!(
+            "{}: {} | {} | {}",
+            b.number,
+            b.path.display(),
+            modified,
+            count
+        );
+    }
+}
+
+/// Save the bookcase, asking how to resolve changes made to the file since it was opened
+fn save(
+    books: &mut books::Bookcase,
+    path: &Path,
+    backups: usize,
+    tui: bool,
+    shelf: Option<&str>,
+) -> Result<(), Error> {
+    loop {
+        match books.close(path, backups) {
+            Err(Error::Conflict(_)) if std::io::stdin().is_terminal() => {}
+            result => return result,
+        }
+        eprint!(
+            "{} was changed since it was opened. [m]erge, [r]eload and discard changes, or [a]bort? ",
+            path.display()
+        );
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        match answer.trim().chars().next() {
+            Some('m') => {
+                let base = books.origin()?.unwrap_or_else(books::Bookcase::new);
+                let theirs = books::Bookcase::open(path)?;
+                eprintln!("Ours are the changes made here, theirs those made to the file");
+                let merged = merge::merge(
+                    &serde_yaml::to_value(&base).map_err(|e| Error::serialise(path, e))?,
+                    &serde_yaml::to_value(&*books).map_err(|e| Error::serialise(path, e))?,
+                    &serde_yaml::to_value(&theirs).map_err(|e| Error::serialise(path, e))?,
+                    ask_conflict,
+                );
+                for conflict in &merged.conflicts {
+                    eprintln!("Conflict: {}", conflict)
+                }
+                books.replace(
+                    serde_yaml::from_value(merged.bookcase).map_err(|e| Error::parse(path, e))?,
+                );
+                books.take_origin(theirs);
+            }
+            Some('r') => {
+                *books = books::Bookcase::open(path)?;
+                if !tui {
+                    return Ok(());
+                }
+                tui::start_tui(books, shelf)?;
+            }
+            _ => return Err(Error::Conflict(path.to_path_buf())),
+        }
+    }
+}
+
+/// Ask which side of a merge conflict to keep
+fn ask_conflict(conflict: &merge::Conflict) -> Option<merge::Side> {
+    eprint!("{}\n[o]urs, [t]heirs or [s]kip? ", conflict);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok()?;
+    match answer.trim().chars().next() {
+        Some('o') => Some(merge::Side::Ours),
+        Some('t') => Some(merge::Side::Theirs),
+        _ => None,
+    }
+}
+
+/// Expand any globs among the files given, keeping other paths as they are
+fn expand_files(files: Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
+    let mut expanded = Vec::new();
+    for file in files {
+        let pattern = file.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            expanded.push(file);
+            continue;
+        }
+        let matches = glob::glob(&pattern)
+            .map_err(|e| Error::Validation(format!("Invalid glob {}: {}", pattern, e)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                let path = e.path().to_path_buf();
+                Error::io(path, e.into())
+            })?;
+        if matches.is_empty() {
+            return Err(Error::Validation(format!("No files match {}", pattern)));
+        }
+        expanded.extend(matches);
     }
+    Ok(expanded)
 }
 
-fn main() {
-    let args = Cli::parse();
+/// The command line from the subcommand onwards, to describe the change in the journal
+fn command_description(args: impl IntoIterator<Item = String>) -> String {
+    let mut args = args.into_iter().skip(1);
+    let mut command = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // The shelf is a global option, so can also follow the subcommand
+            "--shelf" => {
+                args.next();
+            }
+            a if a.starts_with("--shelf=") => {}
+            "-f" | "--file" | "--backups" if command.is_empty() => {
+                args.next();
+            }
+            a if command.is_empty() && a.starts_with('-') => {}
+            _ => command.push(arg),
+        }
+    }
+    command.join(" ")
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("booktop: {}", e);
+            e.exit_code()
+        }
+    }
+}
 
+fn run(args: Cli) -> Result<(), Error> {
     let mut write = !args.dry_run;
 
     let default_file_path = PathBuf::from("bookcase.booktop.yaml");
 
-    let mut file_path = match args.file {
-        Some(path) => Some(path),
-        None => match default_file_path.is_file() {
-            true => Some(default_file_path),
-            false => None,
-        },
+    let mut files = expand_files(args.file)?;
+
+    let mut file_path = match files.len() {
+        0 => Some(default_file_path).filter(|p| p.is_file()),
+        1 => files.pop(),
+        _ => None,
     };
 
+    let tui = matches!(args.command, Commands::Tui { .. });
+    let checking = matches!(
+        args.command,
+        Commands::Util(Util {
+            command: UtilCommands::Check { .. },
+            ..
+        })
+    );
+    // Checking reports why a file cannot be loaded, rather than failing
+    let mut unreadable = None;
+
     let mut books = match (&file_path, args.no_file) {
-        (Some(path), false) => books::Bookcase::open(path),
+        (_, false) if !files.is_empty() => {
+            if !args.command.is_read_only() {
+                return Err(Error::Validation(
+                    "Only reading commands can be used on several files".to_string(),
+                ));
+            }
+            let bookcases = files
+                .iter()
+                .map(|f| Ok((f.display().to_string(), books::Bookcase::open(f)?)))
+                .collect::<Result<_, Error>>()?;
+            books::Bookcase::aggregate(bookcases)
+        }
+        (Some(path), false) if checking => books::Bookcase::open(path).unwrap_or_else(|e| {
+            unreadable = Some(e);
+            books::Bookcase::new()
+        }),
+        (Some(path), false) => books::Bookcase::open(path)?,
         (_, _) => books::Bookcase::new(),
     };
 
+    let shelf = args.shelf.as_deref();
+    let adding = matches!(args.command, Commands::Add { .. } | Commands::Import(_));
+    if let (Some(shelf), false) = (shelf, adding) {
+        books.check_shelf(shelf)?;
+    }
+    let on_shelf = |b: &book::Book| shelf.is_none_or(|s| b.is_on_shelf(s));
+    // Failure reported after saving
+    let mut outcome = Ok(());
+
     match args.command {
         // Bookcase operations
-        Commands::Add { title, author } => {
-            books.add_book(title, author);
+        Commands::Add {
+            title,
+            authors,
+            pages,
+            series,
+            volume,
+            isbn,
+            publisher,
+            year,
+            language,
+            edition,
+            format,
+            ownership,
+        } => {
+            let id = books.add_book(title, authors);
+            let book = books.try_get_mut_book(id)?;
+            book.pages = pages;
+            book.series = series.map(|name| book::Series { name, volume });
+            book.isbn = isbn;
+            book.publisher = publisher;
+            book.year = year;
+            book.language = language;
+            book.edition = edition;
+            book.formats = format.into_iter().collect();
+            book.ownership = ownership;
+            if let Some(shelf) = shelf {
+                books.shelve(id, shelf)?;
+            }
+            for (other, reason) in dedupe::duplicates_of(&books, id, dedupe::DEFAULT_SIMILARITY) {
+                if let Some(b) = books.get_book(&other) {
+                    eprintln!("Possible duplicate of {}: {} ({})", other, b, reason);
+                }
+            }
         }
         Commands::Init { path } => {
-            File::create(&path).expect("Could not create file");
             file_path = Some(path);
         }
-        Commands::List {} => list(&books),
+        Commands::List {
+            field,
+            format,
+            ownership,
+            sort,
+            uuids,
+        } => {
+            let filter = filter::Filter {
+                fields: field,
+                formats: format.into_iter().collect(),
+                ownership: ownership.into_iter().collect(),
+                shelf: args.shelf.clone(),
+                ..Default::default()
+            };
+            list_filtered(&books, &filter, sort.as_ref(), uuids)
+        }
         Commands::Remove { id } => {
-            books.remove_book(id);
+            books.remove_book(books.resolve(&id)?);
         }
         Commands::Pick {} => {
-            let picked = books.pick_book();
+            let filter = filter::Filter {
+                shelf: args.shelf.clone(),
+                ..Default::default()
+            };
+            let picked = books.pick_book(&filter)?;
             println!("{} | {}", picked.0, picked.1)
         }
+        Commands::Next {} => {
+            for (id, book) in books
+                .next_in_series()
+                .into_iter()
+                .filter(|(_, b)| on_shelf(b))
+            {
+                if let Some(series) = &book.series {
+                    println!("{} | {} | {}", series, id, book)
+                }
+            }
+        }
+        Commands::Stats {} => {
+            let selected = books.get_books().into_iter().map(|(_, b)| b);
+            print!("{}", stats::Stats::new(selected.filter(|b| on_shelf(b))))
+        }
         // Book operations
-        Commands::Finish { id } => {
-            if let Some(book) = books.get_mut_book(id) {
-                book.finish();
+        Commands::Finish { id, rating, review } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.finish();
+            if rating.is_some() {
+                book.rating = rating;
+            }
+            match review.as_deref() {
+                Some("") => {
+                    let current = book.review.as_deref().unwrap_or_default();
+                    let edited = util::edit_text(current)?;
+                    book.set_review(&edited);
+                }
+                Some(text) => book.set_review(text),
+                None => {}
             }
         }
         Commands::Start { id } => {
-            if let Some(book) = books.get_mut_book(id) {
-                book.start();
-            }
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.start();
         }
         Commands::Reset { id } => {
-            if let Some(book) = books.get_mut_book(id) {
-                book.reset()
-            }
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.reset()
         }
         Commands::Stop { id } => {
-            if let Some(book) = books.get_mut_book(id) {
-                book.stop()
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.stop()
+        }
+        Commands::Progress {
+            id,
+            progress,
+            pages,
+        } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            if pages.is_some() {
+                book.pages = pages;
+            }
+            book.set_progress(progress)
+        }
+        Commands::Series { id, name, volume } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.series = name.map(|name| book::Series { name, volume });
+        }
+        Commands::Formats { id, formats } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.formats = formats.into_iter().collect();
+        }
+        Commands::Ownership { id, ownership } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.ownership = ownership;
+        }
+        Commands::Lend { id, borrower, due } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            if !book.lend(borrower, due) {
+                let borrower = book.current_loan().map(|l| l.borrower.as_str());
+                return Err(Error::Validation(format!(
+                    "Book {} is already lent to {}",
+                    id,
+                    borrower.unwrap_or_default()
+                )));
+            }
+        }
+        Commands::Return { id } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            if !book.return_loan() {
+                return Err(Error::Validation(format!("Book {} is not lent out", id)));
+            }
+        }
+        Commands::Loans {} => loans(&books, shelf),
+        Commands::Note { id, quote, page } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            let text = match std::io::stdin().is_terminal() {
+                true => util::edit_text(""),
+                false => std::io::read_to_string(std::io::stdin()),
+            }?;
+            let kind = match quote {
+                true => book::NoteKind::Quote,
+                false => book::NoteKind::Note,
+            };
+            if !book.add_note(kind, &text, page) {
+                return Err(Error::Validation("Note is empty, not added".to_string()));
+            }
+        }
+        Commands::Quotes { id } => {
+            let id = id.map(|id| books.resolve(&id)).transpose()?;
+            quotes(&books, id, shelf)
+        }
+        Commands::Set {
+            id,
+            name,
+            value,
+            field_type,
+        } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            let value = match field_type {
+                Some(t) => field::FieldValue::parse_as(&value, t),
+                None => value.parse(),
+            }
+            .map_err(Error::Validation)?;
+            book.fields.insert(name, value);
+        }
+        Commands::Unset { id, name } => {
+            let book = books.try_get_mut_book(books.resolve(&id)?)?;
+            book.fields.remove(&name);
+        }
+        Commands::Shelf(Shelf { command }) => match command {
+            ShelfCommands::List => {
+                for (name, count) in books.get_shelves() {
+                    println!("{}: {} books", name, count)
+                }
+            }
+            ShelfCommands::Create { name } => {
+                if !books.create_shelf(&name) {
+                    return Err(Error::Validation(format!("Shelf {} already exists", name)));
+                }
+            }
+            ShelfCommands::Delete { name } => books.delete_shelf(&name)?,
+            ShelfCommands::Rename { name, new_name } => books.rename_shelf(&name, &new_name)?,
+            ShelfCommands::Add { name, ids } => {
+                for id in ids {
+                    books.shelve(books.resolve(&id)?, &name)?
+                }
+            }
+            ShelfCommands::Remove { name, ids } => {
+                for id in ids {
+                    books.unshelve(books.resolve(&id)?, &name)?
+                }
+            }
+        },
+        Commands::Import(import) => {
+            let export = match import.command {
+                ImportCommands::Goodreads { path } => import::goodreads(&path)?,
+                ImportCommands::Storygraph { path } => import::storygraph(&path)?,
+                ImportCommands::Librarything { path } => import::librarything(&path)?,
+            };
+            println!("{}", export.mapping);
+            let summary = import::import(&mut books, export, shelf);
+            for id in &summary.merged {
+                if let Some(b) = books.get_book(id) {
+                    println!("Merged into {}: {}", id, b);
+                }
+            }
+            for (added, existing, reason) in &summary.possible {
+                if let (Some(a), Some(e)) = (books.get_book(added), books.get_book(existing)) {
+                    println!(
+                        "Added {}: {}, a possible duplicate of {}: {} ({})",
+                        added, a, existing, e, reason
+                    );
+                }
+            }
+            if !summary.possible.is_empty() {
+                println!(
+                    "Check possible duplicates and merge them with \
+                     `booktop util --write dedupe --merge KEEP DUPLICATE`"
+                );
+            }
+            for skipped in &summary.skipped {
+                println!("Skipped {}", skipped);
+            }
+            match args.dry_run {
+                true => println!("{} (dry run, not saved)", summary),
+                false => println!("{}", summary),
             }
         }
+        Commands::Undo {} => match books.undo() {
+            Some(description) => println!("Undone: {}", description),
+            None => return Err(Error::Validation("Nothing to undo".to_string())),
+        },
+        Commands::Redo {} => match books.redo() {
+            Some(description) => println!("Redone: {}", description),
+            None => return Err(Error::Validation("Nothing to redo".to_string())),
+        },
         Commands::Util(util) => {
             write = util.write;
             match util.command {
-                UtilCommands::ExampleBookcase {} => books = util::example_bookcase(),
-                UtilCommands::Renumber {} => {
+                UtilCommands::ExampleBookcase => books.replace(util::example_bookcase()),
+                UtilCommands::Renumber => {
                     books.util_renumber();
                     list(&books)
                 }
+                UtilCommands::Migrate { check } => {
+                    let path = file_path.as_ref().ok_or_else(|| {
+                        Error::Validation("No bookcase file to migrate".to_string())
+                    })?;
+                    let contents = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+                    let mut value =
+                        serde_yaml::from_str(&contents).map_err(|e| Error::parse(path, e))?;
+                    let from = migrate::version(&value);
+                    let changes = migrate::migrate(&mut value)?;
+                    if from == migrate::SCHEMA_VERSION {
+                        println!("{} is up to date", path.display());
+                    } else {
+                        println!(
+                            "{}: version {} -> {}",
+                            path.display(),
+                            from,
+                            migrate::SCHEMA_VERSION
+                        );
+                    }
+                    for change in &changes {
+                        println!("    {}", change)
+                    }
+                    write = util.write && !check && !args.dry_run;
+                    if write {
+                        books.mark_changed()
+                    }
+                    if check && from != migrate::SCHEMA_VERSION {
+                        return Err(Error::Validation(format!(
+                            "{} needs migrating",
+                            path.display()
+                        )));
+                    }
+                    if !util.write && from != migrate::SCHEMA_VERSION {
+                        println!("Not saved, use `util --write migrate` to save the migrated file");
+                    }
+                }
+                UtilCommands::Dedupe { similarity, merge } => {
+                    if let [keep, duplicate] = &merge[..] {
+                        let (keep, duplicate) = (books.resolve(keep)?, books.resolve(duplicate)?);
+                        if let (Some(k), Some(d)) =
+                            (books.get_book(&keep), books.get_book(&duplicate))
+                        {
+                            println!("{}: {}", keep, k);
+                            println!("    {}: {}", duplicate, d);
+                        }
+                        books.merge_duplicate(keep, duplicate)?;
+                        if !write {
+                            println!(
+                                "Run `booktop util --write dedupe --merge {} {}` to merge them",
+                                keep, duplicate
+                            );
+                        }
+                    } else {
+                        let candidates = dedupe::find_duplicates(&books, similarity);
+                        if candidates.is_empty() {
+                            println!("No duplicates found");
+                        }
+                        let mut possible = std::collections::BTreeSet::new();
+                        // Follow books merged earlier to the book they were merged into
+                        let mut merged_into = std::collections::BTreeMap::new();
+                        for c in candidates {
+                            let resolve = |mut id| {
+                                while let Some(into) = merged_into.get(&id) {
+                                    id = *into
+                                }
+                                id
+                            };
+                            let (a, b) = (resolve(c.keep), resolve(c.duplicate));
+                            let (keep, duplicate) = (a.min(b), a.max(b));
+                            let certain = c.reason.is_certain();
+                            if keep == duplicate || !certain && !possible.insert((keep, duplicate))
+                            {
+                                continue;
+                            }
+                            if let (Some(k), Some(d)) =
+                                (books.get_book(&keep), books.get_book(&duplicate))
+                            {
+                                println!("{}: {}", keep, k);
+                                println!("    {}: {} ({})", duplicate, d, c.reason);
+                            }
+                            if !certain {
+                                println!("    Possible duplicate, not merged");
+                                continue;
+                            }
+                            books.merge_duplicate(keep, duplicate)?;
+                            merged_into.insert(duplicate, keep);
+                        }
+                        if !write && !merged_into.is_empty() {
+                            println!("Run `booktop util --write dedupe` to merge them");
+                        }
+                        if !possible.is_empty() {
+                            println!(
+                                "Check possible duplicates and merge them with \
+                                 `booktop util --write dedupe --merge KEEP DUPLICATE`"
+                            );
+                        }
+                    }
+                }
+                UtilCommands::Merge {
+                    base,
+                    ours,
+                    theirs,
+                    output,
+                    driver,
+                } => {
+                    write = false;
+                    let interactive = !driver && std::io::stdin().is_terminal();
+                    let merged = merge::merge(
+                        &merge::load(&base)?,
+                        &merge::load(&ours)?,
+                        &merge::load(&theirs)?,
+                        |c| match interactive {
+                            true => ask_conflict(c),
+                            false => None,
+                        },
+                    );
+                    // Check the result is still a valid bookcase
+                    let bookcase: books::Bookcase = serde_yaml::from_value(merged.bookcase)?;
+                    let contents = serde_yaml::to_string(&bookcase)?;
+                    match (driver, output) {
+                        (true, _) => backup::write_atomic(&ours, contents.as_bytes())?,
+                        (false, Some(path)) => backup::write_atomic(&path, contents.as_bytes())?,
+                        (false, None) => print!("{}", contents),
+                    }
+                    for conflict in &merged.conflicts {
+                        eprintln!("Conflict: {}", conflict)
+                    }
+                    match merged.conflicts.len() {
+                        0 => {}
+                        1 => {
+                            return Err(Error::Validation(
+                                "1 conflict left unresolved, keeping our change".to_string(),
+                            ))
+                        }
+                        n => {
+                            return Err(Error::Validation(format!(
+                                "{} conflicts left unresolved, keeping our changes",
+                                n
+                            )))
+                        }
+                    }
+                }
+                UtilCommands::Check { fix, format } => {
+                    let path = file_path.as_ref().ok_or_else(|| {
+                        Error::Validation("No bookcase file to check".to_string())
+                    })?;
+                    let mut value = match unreadable {
+                        None => {
+                            serde_yaml::to_value(&books).map_err(|e| Error::serialise(path, e))?
+                        }
+                        Some(_) => merge::load(path)?,
+                    };
+                    let fix = fix && unreadable.is_none();
+                    let mut problems = check::check(&mut value, fix);
+                    if let Some(e) = &unreadable {
+                        problems.push(check::Problem::unreadable(e.to_string()));
+                    }
+                    if fix {
+                        books.replace(
+                            serde_yaml::from_value(value).map_err(|e| Error::parse(path, e))?,
+                        );
+                    }
+                    write = util.write && fix && !args.dry_run;
+                    if fix && !util.write && problems.iter().any(|p| p.fixed) {
+                        eprintln!("Not saved, use `util --write check --fix` to save the fixes");
+                    }
+
+                    let report = check::Report::new(path.display().to_string(), &problems);
+                    match format {
+                        check::Output::Text => {
+                            for problem in &problems {
+                                println!("{}", problem)
+                            }
+                            if report.remaining == 0 && report.fixed == 0 {
+                                println!("{}: no problems found", report.file);
+                            }
+                        }
+                        check::Output::Json => println!(
+                            "{}",
+                            serde_json::to_string_pretty(&report)
+                                .map_err(|e| Error::Validation(e.to_string()))?
+                        ),
+                    }
+                    if report.remaining > 0 {
+                        outcome = Err(Error::Validation(format!(
+                            "{} problem{} found in {}",
+                            report.remaining,
+                            if report.remaining == 1 { "" } else { "s" },
+                            report.file
+                        )));
+                    }
+                }
+                UtilCommands::Authors { command } => {
+                    match command.unwrap_or(AuthorCommands::List) {
+                        AuthorCommands::List => authors(&books),
+                        AuthorCommands::Merge { name, aliases } => {
+                            let changed = books.merge_authors(&name, &aliases);
+                            println!(
+                                "Merged into {}, renaming them in {} book{}",
+                                books.canonical_author(&name),
+                                changed,
+                                if changed == 1 { "" } else { "s" }
+                            );
+                        }
+                        AuthorCommands::SortName { name, sort_name } => {
+                            books.set_author_sort_name(&name, sort_name)
+                        }
+                    }
+                }
+                UtilCommands::Restore { number } => {
+                    let path = file_path.as_ref().ok_or_else(|| {
+                        Error::Validation("No bookcase file to restore".to_string())
+                    })?;
+                    match number {
+                        Some(number) => {
+                            books
+                                .replace(books::Bookcase::open(backup::backup_path(path, number))?);
+                            list(&books);
+                            if !write {
+                                println!("Run `booktop util --write restore {}` to restore", number)
+                            }
+                        }
+                        None => restore_list(path),
+                    }
+                }
             }
         }
         Commands::Tui { file } => {
             if let Some(file) = file {
-                books = books::Bookcase::open(file)
+                books = books::Bookcase::open(&file)?;
+                file_path = Some(file);
             };
-            tui::start_tui(&mut books).ok();
+            tui::start_tui(&mut books, shelf)?;
         }
     }
 
@@ -162,9 +1171,67 @@ fn main() {
     }
 
     if write {
-        match &file_path {
-            Some(path) => books.close(path),
-            None => (),
-        };
+        if let Some(path) = &file_path {
+            books.commit(&command_description(std::env::args()));
+            save(&mut books, path, args.backups, tui, shelf)?
+        }
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(args: &str) -> String {
+        command_description(args.split(' ').map(String::from))
+    }
+
+    #[test]
+    fn command_descriptions() {
+        assert_eq!(
+            description("booktop -f b.yaml --dry-run add Emma Jane"),
+            "add Emma Jane"
+        );
+        assert_eq!(
+            description("booktop --shelf Fiction add Emma Jane"),
+            "add Emma Jane"
+        );
+        assert_eq!(
+            description("booktop add Emma --shelf Fiction Jane"),
+            "add Emma Jane"
+        );
+        assert_eq!(description("booktop --shelf=Fiction add Emma"), "add Emma");
+        // Options of the subcommand are kept
+        assert_eq!(
+            description("booktop add Emma Jane --pages 474"),
+            "add Emma Jane --pages 474"
+        );
+    }
+
+    #[test]
+    fn list_leaves_old_file() {
+        let dir = std::env::temp_dir().join(format!("booktop-list-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bookcase.booktop.yaml");
+        let contents = "name: Old\nbooks:\n  1:\n    title: Emma\n    author: Jane Austen\n";
+        std::fs::write(&path, contents).unwrap();
+
+        let file = path.to_str().unwrap();
+        run(Cli::parse_from(["booktop", "--file", file, "list"])).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+        assert!(backup::list(&path).is_empty());
+
+        // Migrating is saved when asked for
+        run(Cli::parse_from([
+            "booktop", "--file", file, "util", "--write", "migrate",
+        ]))
+        .unwrap();
+        let migrated = std::fs::read_to_string(&path).unwrap();
+        assert!(migrated.contains("version: 1"));
+        assert!(migrated.contains("authors:"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,381 @@
+use crate::error::Error;
+use crate::migrate;
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Fields holding sets, serialised as sequences in no particular order
+const SET_FIELDS: [&str; 3] = ["tags", "shelves", "formats"];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+/// A change made differently on both sides, `None` where a side removed the book or field
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub book: String,
+    pub field: Option<String>,
+    pub ours: Option<Value>,
+    pub theirs: Option<Value>,
+}
+
+impl Conflict {
+    /// The side kept when the conflict is not resolved, avoiding losing a changed book
+    fn default_side(&self) -> Side {
+        match (&self.field, &self.ours) {
+            (None, None) => Side::Theirs,
+            _ => Side::Ours,
+        }
+    }
+}
+
+fn describe(value: &Option<Value>) -> String {
+    match value {
+        None => "removed".to_string(),
+        Some(Value::String(s)) => format!("{:?}", s),
+        Some(v) => serde_yaml::to_string(v)
+            .unwrap_or_default()
+            .trim()
+            .replace('\n', " "),
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(
+                f,
+                "{}: {} is {} in ours, {} in theirs",
+                self.book,
+                field,
+                describe(&self.ours),
+                describe(&self.theirs)
+            ),
+            None => match self.ours {
+                Some(_) => write!(f, "{}: changed in ours, removed in theirs", self.book),
+                None => write!(f, "{}: removed in ours, changed in theirs", self.book),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Merge {
+    pub bookcase: Value,
+    /// Conflicts left unresolved, for which the default side was kept
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Read a bookcase file as YAML, upgraded to the current schema version
+pub fn load(path: &Path) -> Result<Value, Error> {
+    let contents = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let mut value = serde_yaml::from_str(&contents).map_err(|e| Error::parse(path, e))?;
+    migrate::migrate(&mut value)?;
+    Ok(value)
+}
+
+/// Three-way merge of bookcases field by field, matching books by UUID
+///
+/// `resolve` is asked about each conflict, the default side being kept when it returns `None`.
+pub fn merge(
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    mut resolve: impl FnMut(&Conflict) -> Option<Side>,
+) -> Merge {
+    let mapping = |v: &'_ Value| v.as_mapping().cloned().unwrap_or_default();
+    let (base, ours, theirs) = (mapping(base), mapping(ours), mapping(theirs));
+    let mut merger = Merger {
+        conflicts: Vec::new(),
+        resolve: &mut resolve,
+    };
+
+    let mut bookcase = Mapping::new();
+    for key in keys([&base, &ours, &theirs]) {
+        let value = match key.as_str() {
+            Some("books") => Some(Value::Mapping(merger.books(
+                books(&base),
+                books(&ours),
+                books(&theirs),
+            ))),
+            _ => merger.value("Bookcase", &key, &base, &ours, &theirs),
+        };
+        if let Some(value) = value {
+            bookcase.insert(key, value);
+        }
+    }
+    Merge {
+        bookcase: Value::Mapping(bookcase),
+        conflicts: merger.conflicts,
+    }
+}
+
+struct Merger<'r> {
+    conflicts: Vec<Conflict>,
+    resolve: &'r mut dyn FnMut(&Conflict) -> Option<Side>,
+}
+
+impl Merger<'_> {
+    fn conflict(&mut self, conflict: Conflict) -> Option<Value> {
+        let side = match (self.resolve)(&conflict) {
+            Some(side) => side,
+            None => {
+                let side = conflict.default_side();
+                self.conflicts.push(conflict.clone());
+                side
+            }
+        };
+        match side {
+            Side::Ours => conflict.ours,
+            Side::Theirs => conflict.theirs,
+        }
+    }
+    /// Merge one field of a mapping
+    fn value(
+        &mut self,
+        book: &str,
+        key: &Value,
+        base: &Mapping,
+        ours: &Mapping,
+        theirs: &Mapping,
+    ) -> Option<Value> {
+        let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+        let field = key.as_str().unwrap_or_default();
+        if o == b {
+            return t.cloned();
+        }
+        if t == b || o == t {
+            return o.cloned();
+        }
+        if let (Some(Value::Sequence(o)), Some(Value::Sequence(t))) = (o, t) {
+            let b = match b {
+                Some(Value::Sequence(b)) => b.as_slice(),
+                _ => &[],
+            };
+            if SET_FIELDS.contains(&field) {
+                return Some(Value::Sequence(merge_sets(b, o, t)));
+            }
+            if let Some(merged) = merge_appends(b, o, t) {
+                return Some(Value::Sequence(merged));
+            }
+        }
+        self.conflict(Conflict {
+            book: book.to_string(),
+            field: Some(field.to_string()),
+            ours: o.cloned(),
+            theirs: t.cloned(),
+        })
+    }
+    fn books(&mut self, base: Vec<Entry>, ours: Vec<Entry>, theirs: Vec<Entry>) -> Mapping {
+        let find = |books: &[Entry], id: &str| books.iter().position(|e| e.id == id);
+        let mut ids: Vec<&str> = ours.iter().map(|e| e.id.as_str()).collect();
+        for e in theirs.iter().chain(&base) {
+            if !ids.contains(&e.id.as_str()) {
+                ids.push(&e.id)
+            }
+        }
+
+        let mut merged: Vec<(Option<u64>, Mapping)> = Vec::new();
+        for id in ids {
+            let b = find(&base, id).map(|i| &base[i]);
+            let o = find(&ours, id).map(|i| &ours[i]);
+            let t = find(&theirs, id).map(|i| &theirs[i]);
+            let label = o.or(t).or(b).map(|e| e.label()).unwrap_or_default();
+            let book = match (b, o, t) {
+                (_, Some(o), Some(t)) => {
+                    let empty = Mapping::new();
+                    let b = b.map_or(&empty, |b| &b.book);
+                    let mut book = Mapping::new();
+                    for key in keys([b, &o.book, &t.book]) {
+                        if let Some(value) = self.value(&label, &key, b, &o.book, &t.book) {
+                            book.insert(key, value);
+                        }
+                    }
+                    Some((o.key, book))
+                }
+                (None, Some(e), None) | (None, None, Some(e)) => Some((e.key, e.book.clone())),
+                (Some(b), Some(e), None) | (Some(b), None, Some(e)) if b.book == e.book => None,
+                (Some(_), o, t) => {
+                    let side = self.conflict(Conflict {
+                        book: label,
+                        field: None,
+                        ours: o.map(|e| Value::Mapping(e.book.clone())),
+                        theirs: t.map(|e| Value::Mapping(e.book.clone())),
+                    });
+                    side.and_then(|_| o.or(t)).map(|e| (e.key, e.book.clone()))
+                }
+                (None, None, None) => None,
+            };
+            if let Some((key, book)) = book {
+                merged.push((key, book));
+            }
+        }
+
+        // Keep each book's number unless already taken, as when both sides added books
+        let mut books = Mapping::new();
+        let mut next = merged.iter().filter_map(|(k, _)| *k).max().unwrap_or(0) + 1;
+        for (key, book) in merged {
+            let key = match key {
+                Some(k) if !books.contains_key(Value::from(k)) => k,
+                _ => {
+                    next += 1;
+                    next - 1
+                }
+            };
+            books.insert(key.into(), Value::Mapping(book));
+        }
+        books
+    }
+}
+
+/// A book with the identity it is matched on
+struct Entry {
+    id: String,
+    key: Option<u64>,
+    book: Mapping,
+}
+
+impl Entry {
+    fn label(&self) -> String {
+        let title = self.book.get("title").and_then(Value::as_str);
+        match (self.key, title) {
+            (Some(k), Some(t)) => format!("Book {} ({})", k, t),
+            (Some(k), None) => format!("Book {}", k),
+            (None, _) => format!("Book {}", self.id),
+        }
+    }
+}
+
+fn books(bookcase: &Mapping) -> Vec<Entry> {
+    let Some(Value::Mapping(books)) = bookcase.get("books") else {
+        return Vec::new();
+    };
+    books
+        .iter()
+        .filter_map(|(key, book)| {
+            let book = book.as_mapping()?.clone();
+            let key = key.as_u64();
+            // Books from before UUIDs can only be matched by number
+            let id = match book.get("uuid").and_then(Value::as_str) {
+                Some(uuid) => uuid.to_string(),
+                None => format!("#{}", key?),
+            };
+            Some(Entry { id, key, book })
+        })
+        .collect()
+}
+
+/// Keys of any of the mappings, in order of first appearance
+fn keys<const N: usize>(mappings: [&Mapping; N]) -> Vec<Value> {
+    let mut keys: Vec<Value> = Vec::new();
+    for key in mappings.iter().flat_map(|m| m.keys()) {
+        if !keys.contains(key) {
+            keys.push(key.clone())
+        }
+    }
+    keys
+}
+
+/// Elements added on either side, less those removed on either side
+fn merge_sets(base: &[Value], ours: &[Value], theirs: &[Value]) -> Vec<Value> {
+    let mut merged: Vec<Value> = ours
+        .iter()
+        .filter(|v| theirs.contains(v) || !base.contains(v))
+        .cloned()
+        .collect();
+    for v in theirs {
+        if !base.contains(v) && !merged.contains(v) {
+            merged.push(v.clone())
+        }
+    }
+    merged
+}
+
+/// Merge lists to which both sides only appended, such as history or notes
+fn merge_appends(base: &[Value], ours: &[Value], theirs: &[Value]) -> Option<Vec<Value>> {
+    if !ours.starts_with(base) || !theirs.starts_with(base) {
+        return None;
+    }
+    let mut merged = ours.to_vec();
+    for v in &theirs[base.len()..] {
+        if !ours[base.len()..].contains(v) {
+            merged.push(v.clone())
+        }
+    }
+    Some(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookcase(books: &str) -> Value {
+        serde_yaml::from_str(&format!("version: 1\nname: Bookcase\nbooks:\n{}", books)).unwrap()
+    }
+
+    #[test]
+    fn merge_fields() {
+        let base = bookcase(
+            "  1:\n    uuid: a\n    title: One\n    authors: [A]\n    read: Unread\n    tags: [x]\n",
+        );
+        let ours = bookcase(
+            "  1:\n    uuid: a\n    title: One\n    authors: [A]\n    read: Read\n    tags: [x, y]\n  2:\n    uuid: b\n    title: Ours\n    authors: [B]\n",
+        );
+        let theirs = bookcase(
+            "  1:\n    uuid: a\n    title: Won\n    authors: [A]\n    read: Unread\n    tags: [z]\n  2:\n    uuid: c\n    title: Theirs\n    authors: [C]\n",
+        );
+
+        let m = merge(&base, &ours, &theirs, |_| None);
+        assert_eq!(m.conflicts, vec![]);
+        let books = &m.bookcase["books"];
+        assert_eq!(books[1]["title"], "Won");
+        assert_eq!(books[1]["read"], "Read");
+        assert_eq!(
+            books[1]["tags"],
+            serde_yaml::from_str::<Value>("[y, z]").unwrap()
+        );
+        assert_eq!(books[2]["title"], "Ours");
+        assert_eq!(books[3]["title"], "Theirs");
+    }
+
+    #[test]
+    fn merge_conflicts() {
+        let base =
+            bookcase("  1:\n    uuid: a\n    title: One\n  2:\n    uuid: b\n    title: Two\n");
+        let ours = bookcase("  1:\n    uuid: a\n    title: Ours\n");
+        let theirs = bookcase(
+            "  1:\n    uuid: a\n    title: Theirs\n  2:\n    uuid: b\n    title: Two, changed\n",
+        );
+
+        let m = merge(&base, &ours, &theirs, |_| None);
+        assert_eq!(m.conflicts.len(), 2);
+        assert_eq!(
+            m.conflicts[0].to_string(),
+            r#"Book 1 (Ours): title is "Ours" in ours, "Theirs" in theirs"#
+        );
+        // The changed book is kept rather than the removal
+        assert_eq!(m.bookcase["books"][1]["title"], "Ours");
+        assert_eq!(m.bookcase["books"][2]["title"], "Two, changed");
+
+        let m = merge(&base, &ours, &theirs, |_| Some(Side::Theirs));
+        assert!(m.conflicts.is_empty());
+        assert_eq!(m.bookcase["books"][1]["title"], "Theirs");
+
+        let m = merge(&base, &ours, &theirs, |_| Some(Side::Ours));
+        assert!(m.bookcase["books"].get(2).is_none());
+    }
+
+    #[test]
+    fn merge_appends_to_lists() {
+        let v = |s: &str| serde_yaml::from_str::<Vec<Value>>(s).unwrap();
+        assert_eq!(
+            merge_appends(&v("[a]"), &v("[a, b]"), &v("[a, c]")),
+            Some(v("[a, b, c]"))
+        );
+        assert_eq!(merge_appends(&v("[a]"), &v("[b]"), &v("[a, c]")), None);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::error::Error;
+use serde_yaml::{Mapping, Value};
+
+/// Schema version written by this version of booktop
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// Each migration upgrades a bookcase from the version of its index to the next, describing
+/// what it changed
+const MIGRATIONS: [fn(&mut Mapping) -> Vec<String>; SCHEMA_VERSION as usize] = [authors_list];
+
+/// Schema version of a bookcase, files from before versioning are version 0
+pub fn version(bookcase: &Value) -> u64 {
+    bookcase.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Upgrade a bookcase to the current schema version, returning a description of each change
+pub fn migrate(bookcase: &mut Value) -> Result<Vec<String>, Error> {
+    let from = version(bookcase);
+    if from > SCHEMA_VERSION {
+        return Err(Error::Validation(format!(
+            "Bookcase has schema version {}, newer than the supported {}",
+            from, SCHEMA_VERSION
+        )));
+    }
+    let mapping = bookcase
+        .as_mapping_mut()
+        .ok_or_else(|| Error::Validation("Bookcase is not a mapping".to_string()))?;
+
+    let mut changes = Vec::new();
+    for (v, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        changes.extend(
+            migration(mapping)
+                .into_iter()
+                .map(|c| format!("{} -> {}: {}", v, v + 1, c)),
+        );
+        mapping.insert("version".into(), (v as u64 + 1).into());
+    }
+    Ok(changes)
+}
+
+fn books_mut(bookcase: &mut Mapping) -> impl Iterator<Item = (String, &mut Mapping)> {
+    bookcase
+        .get_mut("books")
+        .and_then(Value::as_mapping_mut)
+        .into_iter()
+        .flat_map(|books| books.iter_mut())
+        .filter_map(|(id, book)| {
+            let id = match id {
+                Value::Number(n) => n.to_string(),
+                id => id.as_str().unwrap_or_default().to_string(),
+            };
+            book.as_mapping_mut().map(|book| (id, book))
+        })
+}
+
+/// Version 1: a single `author` became a list of `authors`
+fn authors_list(bookcase: &mut Mapping) -> Vec<String> {
+    books_mut(bookcase)
+        .filter_map(|(id, book)| {
+            let author = book.remove("author")?;
+            let authors = match author {
+                Value::Sequence(s) => Value::Sequence(s),
+                a => Value::Sequence(vec![a]),
+            };
+            book.insert("authors".into(), authors);
+            Some(format!("book {}: author renamed to authors", id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::books::Bookcase;
+
+    #[test]
+    fn migrate_unversioned() {
+        let mut v: Value = serde_yaml::from_str(
+            "name: Old\nbooks:\n  1:\n    title: Title\n    author: Author\n    read: Read\n",
+        )
+        .unwrap();
+        assert_eq!(version(&v), 0);
+
+        let changes = migrate(&mut v).unwrap();
+        assert_eq!(changes, vec!["0 -> 1: book 1: author renamed to authors"]);
+        assert_eq!(version(&v), SCHEMA_VERSION);
+        assert_eq!(v["books"][1]["authors"][0], "Author");
+
+        // Already current, nothing to do
+        assert_eq!(migrate(&mut v).unwrap(), Vec::<String>::new());
+
+        let b: Bookcase = serde_yaml::from_value(v).unwrap();
+        assert_eq!(b.books[&1].authors_string(), "Author");
+    }
+
+    #[test]
+    fn newer_version() {
+        let mut v: Value = serde_yaml::from_str("version: 1000\nname: New\nbooks: {}\n").unwrap();
+        assert!(migrate(&mut v).is_err());
+    }
+}
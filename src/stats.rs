@@ -0,0 +1,120 @@
+use crate::book::{Book, Outcome};
+use chrono::{Datelike, Local};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Summary figures for a collection of books
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub books: usize,
+    pub read_states: BTreeMap<String, usize>,
+    pub finished_this_year: usize,
+    pub pages_read: u64,
+    pub rated: usize,
+    pub average_rating: Option<f64>,
+    pub sources: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    pub fn new<'b>(books: impl IntoIterator<Item = &'b Book>) -> Stats {
+        let year = Local::now().year();
+        let mut stats = Stats::default();
+        let mut rating_total = 0.0;
+        for book in books {
+            stats.books += 1;
+            *stats
+                .read_states
+                .entry(book.read_state().to_string())
+                .or_default() += 1;
+            stats.finished_this_year += book
+                .sessions()
+                .iter()
+                .filter(|s| s.outcome == Some(Outcome::Finished))
+                .filter(|s| {
+                    s.end
+                        .is_some_and(|e| e.with_timezone(&Local).year() == year)
+                })
+                .count();
+            // Pages count for each finished read, including those of books being reread
+            let pages = u64::from(book.pages.unwrap_or_default());
+            stats.pages_read = stats
+                .pages_read
+                .saturating_add(pages.saturating_mul(book.read_count() as u64));
+            if let Some(rating) = book.rating {
+                stats.rated += 1;
+                rating_total += rating.stars();
+            }
+            if let Some(source) = &book.source {
+                *stats.sources.entry(source.clone()).or_default() += 1;
+            }
+        }
+        if stats.rated > 0 {
+            stats.average_rating = Some(rating_total / stats.rated as f64);
+        }
+        stats
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Books: {}", self.books)?;
+        for (read, count) in &self.read_states {
+            writeln!(f, "    {}: {}", read, count)?;
+        }
+        writeln!(f, "Finished this year: {}", self.finished_this_year)?;
+        writeln!(f, "Pages read: {}", self.pages_read)?;
+        match self.average_rating {
+            Some(average) => writeln!(
+                f,
+                "Average rating: {:.1} stars from {} rated",
+                average, self.rated
+            )?,
+            None => writeln!(f, "Average rating: none rated")?,
+        }
+        if !self.sources.is_empty() {
+            writeln!(f, "Sources:")?;
+            for (source, count) in &self.sources {
+                writeln!(f, "    {}: {}", source, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::Rating;
+
+    #[test]
+    fn stats() {
+        let mut b1 = Book::new("One".to_string(), vec![]);
+        b1.pages = Some(100);
+        b1.finish();
+        b1.rating = Rating::from_half_stars(8);
+        b1.source = Some("a.yaml".to_string());
+        let mut b2 = Book::new("Two".to_string(), vec![]);
+        b2.pages = Some(300);
+        b2.start();
+        b2.rating = Rating::from_half_stars(5);
+        let mut b3 = Book::new("Three".to_string(), vec![]);
+        b3.pages = Some(50);
+        b3.finish();
+        b3.start();
+
+        let stats = Stats::new([&b1, &b2, &b3]);
+        assert_eq!(stats.books, 3);
+        assert_eq!(stats.read_states["Read"], 1);
+        assert_eq!(stats.read_states["Reading"], 2);
+        assert_eq!(stats.finished_this_year, 2);
+        assert_eq!(stats.pages_read, 150);
+        assert_eq!(stats.average_rating, Some(3.25));
+        assert_eq!(stats.sources["a.yaml"], 1);
+
+        // Pages of long books read several times don't overflow
+        b1.pages = Some(4_000_000_000);
+        b3.pages = Some(4_000_000_000);
+        let stats = Stats::new([&b1, &b3]);
+        assert_eq!(stats.pages_read, 8_000_000_000);
+    }
+}
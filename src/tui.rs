@@ -1,18 +1,22 @@
 use crate::{
-    book::{Book, Read, Sorting as BookSorting},
+    book::{Book, Contributor, Format, Ownership, Rating, Read, Sorting as BookSorting},
     books::Bookcase,
+    field::FieldValue,
     filter::Filter,
+    isbn::Isbn,
 };
+use chrono::Local;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 use rand::Rng;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
-    widgets::{Block, Cell, List, ListState, Paragraph, Row, Table, TableState},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Cell, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap},
     Frame, Terminal,
 };
 use std::{cmp::Ordering, fmt::Display, io, iter::zip, rc::Rc};
@@ -28,6 +32,7 @@ fn move_by(i: usize, δ: isize, l: usize) -> usize {
 enum Popup {
     Book,
     Filter,
+    Shelf,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -36,17 +41,23 @@ struct App<'b> {
     popup: Option<Popup>,
     visible_books: Vec<usize>,
     state: TableState,
+    shelf: Option<String>,
 }
 
 impl<'b> App<'b> {
-    fn new(bookcase: &'b mut Bookcase) -> App {
-        let visible_books = bookcase.books.keys().cloned().collect();
-        App {
+    fn new(bookcase: &'b mut Bookcase, shelf: Option<String>) -> App<'b> {
+        let mut app = App {
             bookcase,
             popup: None,
-            visible_books,
+            visible_books: Vec::new(),
             state: TableState::default().with_selected(Some(0)),
-        }
+            shelf,
+        };
+        app.reset_visible();
+        app
+    }
+    fn on_shelf(&self, book: &Book) -> bool {
+        self.shelf.as_ref().is_none_or(|s| book.is_on_shelf(s))
     }
     fn move_by(&mut self, δ: isize) {
         if let Some(i) = self.state.selected() {
@@ -74,8 +85,43 @@ impl<'b> App<'b> {
         );
         self.visible_books = matches.map(|(&u, _)| u).collect()
     }
+    /// Drop books no longer in the bookcase and append new ones, keeping the current order
+    fn refresh_visible(&mut self) {
+        self.visible_books
+            .retain(|k| self.bookcase.books.contains_key(k));
+        for (k, b) in &self.bookcase.books {
+            if !self.visible_books.contains(k) && self.on_shelf(b) {
+                self.visible_books.push(*k)
+            }
+        }
+        if let Some(i) = self.state.selected() {
+            self.state
+                .select(Some(i.min(self.visible_books.len().saturating_sub(1))))
+        }
+    }
+    fn undo(&mut self) {
+        if self.bookcase.undo().is_some() {
+            self.refresh_visible()
+        }
+    }
+    fn redo(&mut self) {
+        if self.bookcase.redo().is_some() {
+            self.refresh_visible()
+        }
+    }
     fn reset_visible(&mut self) {
-        self.visible_books = self.bookcase.books.keys().cloned().collect()
+        self.visible_books = self
+            .bookcase
+            .books
+            .iter()
+            .filter(|(_, b)| self.on_shelf(b))
+            .map(|(k, _)| *k)
+            .collect()
+    }
+    fn switch_shelf(&mut self, shelf: Option<String>) {
+        self.shelf = shelf;
+        self.reset_visible();
+        self.move_to(0);
     }
     fn sort_by(&mut self, sorting: &BookSorting) {
         let mut books = self
@@ -83,12 +129,12 @@ impl<'b> App<'b> {
             .get_books_by_keys(&self.visible_books)
             .flatten()
             .collect::<Vec<_>>();
-        books.sort_by(|(_, b1), (_, b2)| b1.cmp_by(b2, sorting));
+        books.sort_by(|(_, b1), (_, b2)| self.bookcase.cmp_books(b1, b2, sorting));
         self.visible_books = books.iter().map(|(k, _)| **k).collect();
     }
 }
 
-pub fn start_tui(books: &mut Bookcase) -> Result<(), io::Error> {
+pub fn start_tui(books: &mut Bookcase, shelf: Option<&str>) -> Result<(), io::Error> {
     enable_raw_mode()?;
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
@@ -96,7 +142,7 @@ pub fn start_tui(books: &mut Bookcase) -> Result<(), io::Error> {
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    let mut app = App::new(books);
+    let mut app = App::new(books, shelf.map(String::from));
 
     run_tui(&mut terminal, &mut app)?;
 
@@ -115,12 +161,19 @@ fn run_tui<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(),
                         app.filter_currently_visible(&f)
                     }
                 }
+                Popup::Shelf => {
+                    if let Some(shelf) = run_popup_shelf(terminal, app.bookcase)? {
+                        app.switch_shelf(shelf)
+                    }
+                }
                 Popup::Book => {
                     if let Some(i) = app.state.selected() {
                         if let Some(b) = app.bookcase.get_book(&app.visible_books[i]) {
                             let returned_book = run_popup_book(terminal, b)?;
-                            if let Some(book) = returned_book {
+                            if let (Some(book), false) = (returned_book, app.bookcase.read_only) {
+                                let description = format!("edit {}", book.title);
                                 app.bookcase.books.insert(app.visible_books[i], book);
+                                app.bookcase.commit(&description);
                             }
                         };
                     };
@@ -131,15 +184,16 @@ fn run_tui<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(),
 
         terminal.draw(|rect| draw(rect, app))?;
 
-        if let Some(key) = poll_key()? {
+        if let Some(key) = poll_key_event()? {
             use KeyCode::*;
-            match key {
+            match key.code {
                 Char('q') | Esc => return Ok(()),
                 Char('j') | Down => app.move_by(1),
                 Char('k') | Up => app.move_by(-1),
                 Char('G') => app.move_to(-1),
                 Char('f') => app.popup = Some(Popup::Filter),
                 Char('F') => app.reset_visible(),
+                Char('s') => app.popup = Some(Popup::Shelf),
                 Enter => app.popup = Some(Popup::Book),
                 Char('?') => {
                     let n = rand::thread_rng().gen_range(0..app.visible_books.len()) as isize;
@@ -147,6 +201,10 @@ fn run_tui<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(),
                 }
                 Char('T') => app.sort_by(&BookSorting::Title),
                 Char('A') => app.sort_by(&BookSorting::Author),
+                Char('R') => app.sort_by(&BookSorting::Rating),
+                Char('S') => app.sort_by(&BookSorting::Series),
+                Char('u') => app.undo(),
+                Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => app.redo(),
                 _ => {}
             }
         }
@@ -154,10 +212,14 @@ fn run_tui<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(),
 }
 
 fn poll_key() -> Result<Option<KeyCode>, io::Error> {
+    Ok(poll_key_event()?.map(|key| key.code))
+}
+
+fn poll_key_event() -> Result<Option<KeyEvent>, io::Error> {
     if event::poll(std::time::Duration::from_millis(100))? {
         if let Event::Key(key) = event::read()? {
             if key.kind == KeyEventKind::Press {
-                return Ok(Some(key.code));
+                return Ok(Some(key));
             }
         }
     }
@@ -170,7 +232,14 @@ fn draw(rect: &mut Frame, app: &mut App) {
 
     let highlight_style = Style::default().fg(Color::Yellow);
 
-    let title = Paragraph::new("Booktop");
+    let mut title = "Booktop".to_string();
+    if let Some(shelf) = &app.shelf {
+        title.push_str(&format!(": {}", shelf));
+    }
+    if app.bookcase.read_only {
+        title.push_str(" (read only)");
+    }
+    let title = Paragraph::new(title);
 
     let rows = app
         .bookcase
@@ -182,8 +251,11 @@ fn draw(rect: &mut Frame, app: &mut App) {
         [
             Constraint::Length(3),
             Constraint::Length(3),
+            Constraint::Length(4),
+            Constraint::Length(1),
             Constraint::Min(35),
             Constraint::Length(35),
+            Constraint::Length(PROGRESS_BAR_WIDTH as u16 + 2),
         ],
     )
     .highlight_style(highlight_style);
@@ -192,19 +264,63 @@ fn draw(rect: &mut Frame, app: &mut App) {
     rect.render_stateful_widget(contents, chunks[1], &mut app.state);
 }
 
-fn row_from_book<'b>((i, b): (&'b usize, &'b Book)) -> Row {
+fn row_from_book<'b>((i, b): (&'b usize, &'b Book)) -> Row<'b> {
     Row::new(vec![
         Cell::from(i.to_string()),
         Cell::from(b.read_state().symbol().to_string()),
-        Cell::from(b.title.as_str()),
-        Cell::from(b.author.as_str()),
+        Cell::from(format!(
+            "{}{}",
+            b.ownership.map_or(' ', |o| o.symbol()),
+            b.formats.iter().map(|f| f.symbol()).collect::<String>()
+        )),
+        loan_cell(b),
+        Cell::from(match (&b.series, &b.source) {
+            (Some(series), Some(source)) => format!("{} [{}] ({})", b.title, series, source),
+            (Some(series), None) => format!("{} [{}]", b.title, series),
+            (None, Some(source)) => format!("{} ({})", b.title, source),
+            (None, None) => b.title.clone(),
+        }),
+        Cell::from(b.authors_string()),
+        Cell::from(progress_bar(b)),
     ])
 }
 
+fn loan_cell(b: &Book) -> Cell<'static> {
+    match b.current_loan() {
+        Some(loan) if loan.is_overdue(Local::now().date_naive()) => {
+            Cell::from("!").style(Style::default().fg(Color::Red))
+        }
+        Some(_) => Cell::from(">"),
+        None => Cell::from(""),
+    }
+}
+
+const PROGRESS_BAR_WIDTH: usize = 10;
+
+fn progress_bar(b: &Book) -> String {
+    if b.read_state() != &Read::Reading {
+        return String::new();
+    }
+    match (b.progress_fraction(), b.progress) {
+        (Some(fraction), _) => {
+            let filled = (fraction * PROGRESS_BAR_WIDTH as f64).round() as usize;
+            format!(
+                "[{}{}]",
+                "#".repeat(filled),
+                " ".repeat(PROGRESS_BAR_WIDTH - filled)
+            )
+        }
+        (None, Some(progress)) => format!("p. {}", progress),
+        (None, None) => String::new(),
+    }
+}
+
 #[derive(Debug, Eq, PartialEq)]
 enum FilterPopupField {
     Author,
     Read,
+    Format,
+    Ownership,
     Tags,
 }
 
@@ -213,7 +329,9 @@ impl FilterPopupField {
         use FilterPopupField::*;
         match self {
             Author => Read,
-            Read => Tags,
+            Read => Format,
+            Format => Ownership,
+            Ownership => Tags,
             Tags => Author,
         }
     }
@@ -221,6 +339,7 @@ impl FilterPopupField {
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
 enum SelectionChange {
+    #[allow(dead_code)]
     Select,
     Deselect,
     Toggle,
@@ -270,7 +389,7 @@ where
     fn deactivate(&mut self) {
         self.state.select(None)
     }
-    fn as_stateful_list(&mut self) -> (List, &mut ListState) {
+    fn as_stateful_list(&mut self) -> (List<'_>, &mut ListState) {
         let list = List::new(self.values.iter().enumerate().map(|(i, e)| {
             let selected = selected_symbol(self.selected[i]);
             format!("[{selected}] {e}")
@@ -283,6 +402,8 @@ where
 struct FilterPopupApp {
     authors: SelectableList<Rc<str>>,
     read: SelectableList<Read>,
+    formats: SelectableList<Format>,
+    ownership: SelectableList<Ownership>,
     tags: SelectableList<String>,
     current_field: FilterPopupField,
 }
@@ -290,13 +411,21 @@ struct FilterPopupApp {
 impl FilterPopupApp {
     fn new(books: &Bookcase) -> Self {
         let read = SelectableList::new(&Read::all());
-        let author_list: Vec<_> = books.get_authors().iter().map(|a| Rc::from(*a)).collect();
+        let formats = SelectableList::new(&Format::all());
+        let ownership = SelectableList::new(&Ownership::all());
+        let author_list: Vec<_> = books
+            .get_authors()
+            .iter()
+            .map(|a| Rc::from(a.as_str()))
+            .collect();
         let mut authors = SelectableList::new(&author_list);
         let tags = SelectableList::new(&books.get_tags());
         authors.activate();
         FilterPopupApp {
             authors,
             read,
+            formats,
+            ownership,
             tags,
             current_field: FilterPopupField::Author,
         }
@@ -305,6 +434,8 @@ impl FilterPopupApp {
         match self.current_field {
             FilterPopupField::Author => self.authors.move_by(δ),
             FilterPopupField::Read => self.read.move_by(δ),
+            FilterPopupField::Format => self.formats.move_by(δ),
+            FilterPopupField::Ownership => self.ownership.move_by(δ),
             FilterPopupField::Tags => self.tags.move_by(δ),
         }
     }
@@ -312,6 +443,8 @@ impl FilterPopupApp {
         match self.current_field {
             FilterPopupField::Author => self.authors.change_selection(SelectionChange::Toggle),
             FilterPopupField::Read => self.read.change_selection(SelectionChange::Toggle),
+            FilterPopupField::Format => self.formats.change_selection(SelectionChange::Toggle),
+            FilterPopupField::Ownership => self.ownership.change_selection(SelectionChange::Toggle),
             FilterPopupField::Tags => self.tags.change_selection(SelectionChange::Toggle),
         }
     }
@@ -319,6 +452,10 @@ impl FilterPopupApp {
         match self.current_field {
             FilterPopupField::Author => self.authors.change_selection(SelectionChange::Deselect),
             FilterPopupField::Read => self.read.change_selection(SelectionChange::Deselect),
+            FilterPopupField::Format => self.formats.change_selection(SelectionChange::Deselect),
+            FilterPopupField::Ownership => {
+                self.ownership.change_selection(SelectionChange::Deselect)
+            }
             FilterPopupField::Tags => self.tags.change_selection(SelectionChange::Deselect),
         }
     }
@@ -326,12 +463,16 @@ impl FilterPopupApp {
         match self.current_field {
             FilterPopupField::Author => self.authors.deactivate(),
             FilterPopupField::Read => self.read.deactivate(),
+            FilterPopupField::Format => self.formats.deactivate(),
+            FilterPopupField::Ownership => self.ownership.deactivate(),
             FilterPopupField::Tags => self.tags.deactivate(),
         }
         self.current_field = new_field;
         match self.current_field {
             FilterPopupField::Author => self.authors.activate(),
             FilterPopupField::Read => self.read.activate(),
+            FilterPopupField::Format => self.formats.activate(),
+            FilterPopupField::Ownership => self.ownership.activate(),
             FilterPopupField::Tags => self.tags.activate(),
         }
     }
@@ -347,6 +488,14 @@ impl FilterPopupApp {
                 .filter_map(|(r, b)| b.then_some(r))
                 .collect(),
             tags: Vec::new(),
+            fields: Vec::new(),
+            formats: zip(self.formats.values, self.formats.selected)
+                .filter_map(|(f, b)| b.then_some(f))
+                .collect(),
+            ownership: zip(self.ownership.values, self.ownership.selected)
+                .filter_map(|(o, b)| b.then_some(o))
+                .collect(),
+            shelf: None,
         }
     }
 }
@@ -374,6 +523,45 @@ fn run_popup_filter<B: Backend>(
     }
 }
 
+/// Choose a shelf to show, `Some(None)` being all books
+fn run_popup_shelf<B: Backend>(
+    terminal: &mut Terminal<B>,
+    books: &Bookcase,
+) -> Result<Option<Option<String>>, io::Error> {
+    let shelves: Vec<(Option<String>, String)> = std::iter::once((None, "All books".to_string()))
+        .chain(
+            books
+                .get_shelves()
+                .into_iter()
+                .map(|(s, n)| (Some(s.to_string()), format!("{} ({})", s, n))),
+        )
+        .collect();
+    let mut state = ListState::default().with_selected(Some(0));
+    loop {
+        terminal.draw(|rect| draw_popup_shelf(rect, &shelves, &mut state))?;
+        if let Some(key) = poll_key()? {
+            use KeyCode::*;
+            let i = state.selected().unwrap_or_default();
+            match key {
+                Enter => return Ok(Some(shelves[i].0.clone())),
+                Char('k') | Up => state.select(Some(move_by(i, -1, shelves.len()))),
+                Char('j') | Down => state.select(Some(move_by(i, 1, shelves.len()))),
+                Esc => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn draw_popup_shelf(f: &mut Frame, shelves: &[(Option<String>, String)], state: &mut ListState) {
+    let area = popup_rect(40, 50, f.size());
+    let highlight_style = Style::default().fg(Color::Yellow);
+    let list = List::new(shelves.iter().map(|(_, label)| label.as_str()))
+        .block(Block::bordered().title("Shelves"))
+        .highlight_style(highlight_style);
+    f.render_stateful_widget(list, area, state);
+}
+
 fn selected_symbol(b: bool) -> &'static str {
     match b {
         true => "X",
@@ -393,11 +581,17 @@ fn draw_popup_filter(f: &mut Frame, app: &mut FilterPopupApp) {
         Direction::Vertical,
         [
             Constraint::Min(1),
-            Constraint::Length(4),
+            Constraint::Length(7),
             Constraint::Min(1),
         ],
     );
     let popup_filter_layout = popup_filter_layout_vertical.split(area);
+    let popup_filter_layout_states = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+    ])
+    .split(popup_filter_layout[1]);
 
     let author_block = Block::bordered().title("Author");
     let (author_list, author_state) = app.authors.as_stateful_list();
@@ -409,7 +603,25 @@ fn draw_popup_filter(f: &mut Frame, app: &mut FilterPopupApp) {
     let read_block = Block::bordered().title("Read");
     let (read_list, read_state) = app.read.as_stateful_list();
     let read_list = read_list.block(read_block).highlight_style(highlight_style);
-    f.render_stateful_widget(read_list, popup_filter_layout[1], read_state);
+    f.render_stateful_widget(read_list, popup_filter_layout_states[0], read_state);
+
+    let formats_block = Block::bordered().title("Format");
+    let (formats_list, formats_state) = app.formats.as_stateful_list();
+    let formats_list = formats_list
+        .block(formats_block)
+        .highlight_style(highlight_style);
+    f.render_stateful_widget(formats_list, popup_filter_layout_states[1], formats_state);
+
+    let ownership_block = Block::bordered().title("Ownership");
+    let (ownership_list, ownership_state) = app.ownership.as_stateful_list();
+    let ownership_list = ownership_list
+        .block(ownership_block)
+        .highlight_style(highlight_style);
+    f.render_stateful_widget(
+        ownership_list,
+        popup_filter_layout_states[2],
+        ownership_state,
+    );
 
     let tags_block = Block::bordered().title("Tags");
     let (tags_list, tags_state) = app.tags.as_stateful_list();
@@ -443,15 +655,100 @@ enum BookPopupField {
     Title,
     Author,
     Read,
+    Rating,
+    Isbn,
+    Publisher,
+    Year,
+    Edition,
+    Language,
     Tags,
+    Fields,
+    Review,
+}
+
+impl BookPopupField {
+    fn next(&self) -> Self {
+        use BookPopupField::*;
+        match self {
+            Title => Author,
+            Author => Read,
+            Read => Rating,
+            Rating => Isbn,
+            Isbn => Publisher,
+            Publisher => Year,
+            Year => Edition,
+            Edition => Language,
+            Language => Tags,
+            Tags => Fields,
+            Fields => Review,
+            Review => Title,
+        }
+    }
 }
 
 const SEPARATOR_CHAR: char = ',';
+const CONTRIBUTOR_SEPARATOR_CHAR: char = ';';
+const FIELD_SEPARATOR_CHAR: char = ';';
+const FIELD_VALUE_CHAR: char = '=';
+const FIELD_ESCAPE_CHAR: char = '\\';
+
+/// Escape the separators in a field name or value, so that they can be typed as text
+fn escape_field(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if [FIELD_SEPARATOR_CHAR, FIELD_VALUE_CHAR, FIELD_ESCAPE_CHAR].contains(&c) {
+            escaped.push(FIELD_ESCAPE_CHAR)
+        }
+        escaped.push(c)
+    }
+    escaped
+}
+
+/// Split the text of fields, as `key=value; key=value`, into names and values
+fn split_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let (mut key, mut value) = (String::new(), None);
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            FIELD_ESCAPE_CHAR => chars.next(),
+            FIELD_VALUE_CHAR if value.is_none() => {
+                value = Some(String::new());
+                None
+            }
+            FIELD_SEPARATOR_CHAR => {
+                if let Some(value) = value.take() {
+                    fields.push((key.trim().to_string(), value.trim().to_string()))
+                }
+                key.clear();
+                None
+            }
+            c => Some(c),
+        };
+        if let Some(c) = literal {
+            value.as_mut().unwrap_or(&mut key).push(c)
+        }
+    }
+    if let Some(value) = value {
+        fields.push((key.trim().to_string(), value.trim().to_string()))
+    }
+    fields.retain(|(k, _)| !k.is_empty());
+    fields
+}
 
 #[derive(Debug, Eq, PartialEq)]
 struct BookPopupApp {
     book: Book,
+    authors: String,
     tags: Vec<String>,
+    review: String,
+    isbn: String,
+    publisher: String,
+    year: String,
+    edition: String,
+    language: String,
+    fields: String,
+    notes_state: ListState,
     current_field: BookPopupField,
 }
 
@@ -459,28 +756,76 @@ impl BookPopupApp {
     fn new(book: &Book) -> Self {
         let book = book.clone();
         let tags = book.tags.iter().cloned().collect();
+        let review = book.review.clone().unwrap_or_default();
+        let authors = book.authors_string();
+        let isbn = book
+            .isbn
+            .as_ref()
+            .map(|i| i.to_string())
+            .unwrap_or_default();
+        let publisher = book.publisher.clone().unwrap_or_default();
+        let year = book.year.map(|y| y.to_string()).unwrap_or_default();
+        let edition = book.edition.clone().unwrap_or_default();
+        let language = book.language.clone().unwrap_or_default();
+        let fields = book
+            .fields
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "{}{}{}",
+                    escape_field(k),
+                    FIELD_VALUE_CHAR,
+                    escape_field(&v.to_string())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(&format!("{} ", FIELD_SEPARATOR_CHAR));
         BookPopupApp {
             book,
+            authors,
             current_field: BookPopupField::Title,
             tags,
+            review,
+            isbn,
+            publisher,
+            year,
+            edition,
+            language,
+            fields,
+            notes_state: ListState::default(),
+        }
+    }
+    fn scroll_notes(&mut self, δ: isize) {
+        if !self.book.notes.is_empty() {
+            let i = self.notes_state.selected().unwrap_or(0);
+            let j = move_by(i, δ, self.book.notes.len());
+            self.notes_state.select(Some(j))
         }
     }
     fn tab(&mut self) {
-        self.current_field = match self.current_field {
-            BookPopupField::Title => BookPopupField::Author,
-            BookPopupField::Author => BookPopupField::Read,
-            BookPopupField::Read => BookPopupField::Tags,
-            BookPopupField::Tags => BookPopupField::Title,
+        self.current_field = self.current_field.next()
+    }
+    /// The text being edited, for fields which are edited as plain text
+    fn text_buffer(&mut self) -> Option<&mut String> {
+        match self.current_field {
+            BookPopupField::Title => Some(&mut self.book.title),
+            BookPopupField::Author => Some(&mut self.authors),
+            BookPopupField::Review => Some(&mut self.review),
+            BookPopupField::Isbn => Some(&mut self.isbn),
+            BookPopupField::Publisher => Some(&mut self.publisher),
+            BookPopupField::Year => Some(&mut self.year),
+            BookPopupField::Edition => Some(&mut self.edition),
+            BookPopupField::Language => Some(&mut self.language),
+            BookPopupField::Fields => Some(&mut self.fields),
+            _ => None,
         }
     }
     fn backspace(&mut self) {
+        if let Some(text) = self.text_buffer() {
+            text.pop();
+            return;
+        }
         match self.current_field {
-            BookPopupField::Author => {
-                self.book.author.pop();
-            }
-            BookPopupField::Title => {
-                self.book.title.pop();
-            }
             BookPopupField::Tags => {
                 if let Some(mut t) = self.tags.pop() {
                     if t.pop().is_some() {
@@ -488,13 +833,16 @@ impl BookPopupApp {
                     }
                 }
             }
+            BookPopupField::Rating => self.book.rating = None,
             _ => {}
         }
     }
     fn input(&mut self, value: char) {
+        if let Some(text) = self.text_buffer() {
+            text.push(value);
+            return;
+        }
         match self.current_field {
-            BookPopupField::Author => self.book.author.push(value),
-            BookPopupField::Title => self.book.title.push(value),
             BookPopupField::Tags => match self.tags.pop() {
                 Some(mut t) => match value {
                     SEPARATOR_CHAR => {
@@ -508,14 +856,75 @@ impl BookPopupApp {
                 },
                 None => self.tags.push(value.to_string()),
             },
+            BookPopupField::Read => match value {
+                's' => self.book.start(),
+                'f' => self.book.finish(),
+                'p' => self.book.stop(),
+                'u' => self.book.reset(),
+                _ => {}
+            },
+            BookPopupField::Rating => {
+                let half_stars = self.book.rating.map_or(0, |r| r.half_stars());
+                let half_stars = match value {
+                    '+' => half_stars.saturating_add(1).min(Rating::MAX_HALF_STARS),
+                    '-' => half_stars.saturating_sub(1),
+                    _ => half_stars,
+                };
+                self.book.rating = Rating::from_half_stars(half_stars)
+            }
             _ => {}
         }
     }
     fn into_book(self) -> Book {
         let mut book = self.book;
+        let authors: Vec<Contributor> = self
+            .authors
+            .split(CONTRIBUTOR_SEPARATOR_CHAR)
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(|a| a.parse().unwrap_or_else(|_| Contributor::from(a)))
+            .collect();
+        if !authors.is_empty() {
+            book.authors = authors;
+        }
         book.tags = self.tags.into_iter().collect();
+        book.set_review(&self.review);
+        // Keep the previous values where the new text is not valid
+        match self.isbn.trim() {
+            "" => book.isbn = None,
+            isbn => book.isbn = isbn.parse().ok().or(book.isbn),
+        }
+        match self.year.trim() {
+            "" => book.year = None,
+            year => book.year = year.parse().ok().or(book.year),
+        }
+        book.publisher = non_empty(&self.publisher);
+        book.edition = non_empty(&self.edition);
+        book.language = non_empty(&self.language);
+        // Unchanged fields keep their value, and changed fields their type where it still fits
+        book.fields = split_fields(&self.fields)
+            .into_iter()
+            .filter_map(|(k, v)| {
+                let value = match book.fields.get(&k) {
+                    Some(old) if old.to_string() == v => Some(old.clone()),
+                    Some(old) => FieldValue::parse_as(&v, old.field_type())
+                        .ok()
+                        .or_else(|| v.parse().ok()),
+                    None => v.parse().ok(),
+                };
+                Some((k, value?))
+            })
+            .collect();
         book
     }
+    fn isbn_is_valid(&self) -> bool {
+        self.isbn.trim().is_empty() || self.isbn.parse::<Isbn>().is_ok()
+    }
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
 }
 
 fn run_popup_book<B: Backend>(
@@ -532,6 +941,8 @@ fn run_popup_book<B: Backend>(
                 Esc => return Ok(None),
                 Tab => app_popup.tab(),
                 Backspace => app_popup.backspace(),
+                Up => app_popup.scroll_notes(-1),
+                Down => app_popup.scroll_notes(1),
                 Char(value) => app_popup.input(value),
                 _ => {}
             }
@@ -550,6 +961,8 @@ fn draw_popup_book(f: &mut Frame, app: &mut BookPopupApp) {
         Constraint::Min(3),
         Constraint::Min(3),
         Constraint::Min(3),
+        Constraint::Min(3),
+        Constraint::Min(5),
         Constraint::Fill(1),
     ]);
     let popup_book_layout = popup_book_layout_vertical.split(area);
@@ -562,30 +975,137 @@ fn draw_popup_book(f: &mut Frame, app: &mut BookPopupApp) {
         block_selected_style,
     );
     let author_block = block_border_style_if(
-        Block::bordered().title("Author"),
+        Block::bordered().title("Authors"),
         app.current_field == BookPopupField::Author,
         block_selected_style,
     );
     let read_block = block_border_style_if(
-        Block::bordered().title("Read"),
+        Block::bordered().title("Read ([s]tart, [f]inish, [p]ause, [u]nread)"),
         app.current_field == BookPopupField::Read,
         block_selected_style,
     );
+    let rating_block = block_border_style_if(
+        Block::bordered().title("Rating ([+]/[-])"),
+        app.current_field == BookPopupField::Rating,
+        block_selected_style,
+    );
+    let isbn_block = block_border_style_if(
+        Block::bordered().title(match app.isbn_is_valid() {
+            true => "ISBN",
+            false => "ISBN (invalid)",
+        }),
+        app.current_field == BookPopupField::Isbn,
+        block_selected_style,
+    );
+    let publisher_block = block_border_style_if(
+        Block::bordered().title("Publisher"),
+        app.current_field == BookPopupField::Publisher,
+        block_selected_style,
+    );
+    let year_block = block_border_style_if(
+        Block::bordered().title("Year"),
+        app.current_field == BookPopupField::Year,
+        block_selected_style,
+    );
+    let edition_block = block_border_style_if(
+        Block::bordered().title("Edition"),
+        app.current_field == BookPopupField::Edition,
+        block_selected_style,
+    );
+    let language_block = block_border_style_if(
+        Block::bordered().title("Language"),
+        app.current_field == BookPopupField::Language,
+        block_selected_style,
+    );
     let tags_block = block_border_style_if(
         Block::bordered().title("Tags"),
         app.current_field == BookPopupField::Tags,
         block_selected_style,
     );
+    let fields_block = block_border_style_if(
+        Block::bordered().title("Fields (name=value; \\ escapes ; and =)"),
+        app.current_field == BookPopupField::Fields,
+        block_selected_style,
+    );
+    let review_block = block_border_style_if(
+        Block::bordered().title("Review"),
+        app.current_field == BookPopupField::Review,
+        block_selected_style,
+    );
+    let sessions_block =
+        Block::bordered().title(format!("Sessions ({} reads)", app.book.read_count()));
+    let history_block = Block::bordered().title("History");
+    let notes_block = Block::bordered().title("Notes ([↑]/[↓])");
 
     let title = Paragraph::new(app.book.title.as_str()).block(title_block);
-    let read = Paragraph::new(app.book.read_state().to_string()).block(read_block);
-    let author = Paragraph::new(app.book.author.as_str()).block(author_block);
+    let read = match app.book.current_session() {
+        Some(session) => format!("{} (current session {})", app.book.read_state(), session),
+        None => app.book.read_state().to_string(),
+    };
+    let read = Paragraph::new(read).block(read_block);
+    let author = Paragraph::new(app.authors.as_str()).block(author_block);
+    let rating = match app.book.rating {
+        Some(r) => format!("{} {}", r.symbols(), r),
+        None => String::new(),
+    };
+    let rating = Paragraph::new(rating).block(rating_block);
+    let review = Paragraph::new(app.review.as_str())
+        .wrap(Wrap { trim: false })
+        .block(review_block);
+    let fields = Paragraph::new(app.fields.as_str()).block(fields_block);
+    let isbn = Paragraph::new(app.isbn.as_str()).block(isbn_block);
+    let publisher = Paragraph::new(app.publisher.as_str()).block(publisher_block);
+    let year = Paragraph::new(app.year.as_str()).block(year_block);
+    let edition = Paragraph::new(app.edition.as_str()).block(edition_block);
+    let language = Paragraph::new(app.language.as_str()).block(language_block);
     let tags = Paragraph::new(app.tags.join(&SEPARATOR_CHAR.to_string())).block(tags_block);
+    let sessions =
+        List::new(app.book.sessions().iter().map(|s| s.to_string())).block(sessions_block);
+    let notes = List::new(app.book.notes.iter().map(|n| {
+        let mut lines = vec![Line::styled(
+            n.heading(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        lines.extend(n.text.lines().map(Line::raw));
+        ListItem::new(lines)
+    }))
+    .block(notes_block)
+    .highlight_style(block_selected_style);
+    let history = List::new(app.book.history().iter().map(|c| c.to_string())).block(history_block);
 
     f.render_widget(title, popup_book_layout[0]);
     f.render_widget(author, popup_book_layout[1]);
-    f.render_widget(read, popup_book_layout[2]);
-    f.render_widget(tags, popup_book_layout[3]);
+    let popup_book_layout_read =
+        Layout::horizontal([Constraint::Fill(2), Constraint::Fill(1)]).split(popup_book_layout[2]);
+    f.render_widget(read, popup_book_layout_read[0]);
+    f.render_widget(rating, popup_book_layout_read[1]);
+    let popup_book_layout_details = Layout::horizontal([
+        Constraint::Length(19),
+        Constraint::Fill(2),
+        Constraint::Length(8),
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+    ])
+    .split(popup_book_layout[3]);
+    f.render_widget(isbn, popup_book_layout_details[0]);
+    f.render_widget(publisher, popup_book_layout_details[1]);
+    f.render_widget(year, popup_book_layout_details[2]);
+    f.render_widget(edition, popup_book_layout_details[3]);
+    f.render_widget(language, popup_book_layout_details[4]);
+    let popup_book_layout_tags =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).split(popup_book_layout[4]);
+    f.render_widget(tags, popup_book_layout_tags[0]);
+    f.render_widget(fields, popup_book_layout_tags[1]);
+    f.render_widget(review, popup_book_layout[5]);
+    let popup_book_layout_bottom = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+        Constraint::Fill(2),
+    ])
+    .split(popup_book_layout[6]);
+    f.render_widget(sessions, popup_book_layout_bottom[0]);
+    f.render_widget(history, popup_book_layout_bottom[1]);
+    f.render_stateful_widget(notes, popup_book_layout_bottom[2], &mut app.notes_state);
 }
 
 fn block_border_style_if(block: Block, cond: bool, style: Style) -> Block {
@@ -603,9 +1123,9 @@ mod tests {
     fn test_sorting() {
         let mut books = Bookcase::new();
         for (t, a) in zip(["b", "a", "c"], ["G", "A", "F"]) {
-            books.add_book(t.to_string(), a.to_string());
+            books.add_book(t.to_string(), vec![a.into()]);
         }
-        let mut app = App::new(&mut books);
+        let mut app = App::new(&mut books, None);
 
         app.sort_by(&BookSorting::Title);
         assert_eq!(app.visible_books, vec![2, 1, 3]);
@@ -613,4 +1133,26 @@ mod tests {
         app.sort_by(&BookSorting::Author);
         assert_eq!(app.visible_books, vec![2, 3, 1]);
     }
+
+    #[test]
+    fn edit_fields() {
+        let mut book = Book::new("Emma".to_string(), vec!["Jane Austen".into()]);
+        let text = |s: &str| FieldValue::String(s.to_string());
+        book.fields.insert("shelf".to_string(), text("12"));
+        book.fields.insert("note".to_string(), text("a=b; c"));
+        book.fields
+            .insert("price".to_string(), FieldValue::Number(8.0));
+
+        let app = BookPopupApp::new(&book);
+        assert_eq!(app.fields, "note=a\\=b\\; c; price=8; shelf=12");
+        assert_eq!(app.into_book().fields, book.fields);
+
+        let mut app = BookPopupApp::new(&book);
+        app.fields = "price=9.5; shelf=13; year=2024".to_string();
+        let fields = app.into_book().fields;
+        assert_eq!(fields["price"], FieldValue::Number(9.5));
+        assert_eq!(fields["shelf"], text("13"));
+        assert_eq!(fields["year"], FieldValue::Number(2024.0));
+        assert!(!fields.contains_key("note"));
+    }
 }
@@ -1,14 +1,36 @@
 use crate::books::Bookcase;
+use std::env;
+use std::fs;
+use std::io;
+use std::process::Command;
 
 pub fn example_bookcase() -> Bookcase {
     let mut books = Bookcase::new();
     books.add_book(
         "Great Expectations".to_string(),
-        "Charles Dickens".to_string(),
+        vec!["Charles Dickens".into()],
     );
     books.add_book(
         "Journey to the Center of the Earth".to_string(),
-        "Jules Verne".to_string(),
+        vec!["Jules Verne".into()],
     );
     books
 }
+
+/// Open `$VISUAL` or `$EDITOR` on `initial`, returning the edited text
+pub fn edit_text(initial: &str) -> io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let path = env::temp_dir().join(format!("booktop-{}.txt", std::process::id()));
+    fs::write(&path, initial)?;
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program).args(words).arg(&path).status();
+    let text = fs::read_to_string(&path);
+    fs::remove_file(&path).ok();
+    match status? {
+        s if s.success() => text,
+        s => Err(io::Error::other(format!("{} exited with {}", editor, s))),
+    }
+}